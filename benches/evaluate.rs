@@ -0,0 +1,70 @@
+use std::io::Cursor;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use xpather::parser::Tokenizer;
+use xpather::{parse_document, Document};
+
+const QUERIES: [&str; 5] = [
+    r#"//div"#,
+    r#"//*[@class]"#,
+    r#"/html/body/div/a"#,
+    r#"//section//section//p[last()]"#,
+    r#"//div[contains(@class, "item")]/a[starts-with(@href, "/page/")]"#,
+];
+
+// A wide list of items followed by a deeply nested set of sections.
+fn build_page() -> String {
+    let items = (0..500)
+        .map(|i| format!(r#"<div class="item item-{i}"><a href="/page/{i}">Item {i}</a></div>"#))
+        .collect::<String>();
+
+    let sections = "<section><p>Nested</p>".repeat(100) + &"</section>".repeat(100);
+
+    format!("<html><body>{items}{sections}</body></html>")
+}
+
+fn document() -> Document {
+    parse_document(&mut Cursor::new(build_page())).unwrap()
+}
+
+fn tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+
+    for query in QUERIES {
+        group.bench_function(query, |b| {
+            b.iter(|| Tokenizer::new(black_box(query)).count())
+        });
+    }
+
+    group.finish();
+}
+
+// Evaluating is lazy, creating the iterator only tokenizes and parses the query.
+fn parse(c: &mut Criterion) {
+    let doc = document();
+    let mut group = c.benchmark_group("parse");
+
+    for query in QUERIES {
+        group.bench_function(query, |b| {
+            b.iter(|| doc.evaluate(black_box(query)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn evaluate(c: &mut Criterion) {
+    let doc = document();
+    let mut group = c.benchmark_group("evaluate");
+
+    for query in QUERIES {
+        group.bench_function(query, |b| {
+            b.iter(|| doc.evaluate(black_box(query)).unwrap().count())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, tokenize, parse, evaluate);
+criterion_main!(benches);
@@ -1,7 +1,17 @@
 // What we'll be iterating through.
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::functions::FunctionLibrary;
 use crate::value;
-use crate::{AxisName, Document, Node, NodeTest, Nodeset};
+use crate::{AxisName, Document, Node, NodeTest, Nodeset, Value};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EvaluationOptions {
+    /// Exclude comment and processing-instruction nodes from `node()` tests.
+    /// Explicit `comment()` and `processing-instruction()` tests are unaffected.
+    pub skip_comments_and_pis: bool,
+}
 
 pub struct Evaluation<'a> {
     pub document: &'a Document,
@@ -9,6 +19,18 @@ pub struct Evaluation<'a> {
 
     pub position: usize,
     pub size: usize,
+
+    pub options: EvaluationOptions,
+
+    // Unique to each evaluation, even of the same node. See `Evaluation::id`.
+    id: usize,
+}
+
+// Each evaluation takes the next id.
+fn next_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
 }
 
 impl<'a> Evaluation<'a> {
@@ -18,6 +40,28 @@ impl<'a> Evaluation<'a> {
             node,
             position: 1,
             size: 1,
+            options: EvaluationOptions::default(),
+            id: next_id(),
+        }
+    }
+
+    /// Identifies this evaluation. A predicate evaluates each node it checks with a new one,
+    /// even when the same node is checked again from another starting node.
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Evaluate from `node` as if it were at `position` of a context holding `size` nodes.
+    pub fn new_with_context(
+        node: &'a Node,
+        document: &'a Document,
+        position: usize,
+        size: usize,
+    ) -> Evaluation<'a> {
+        Evaluation {
+            position,
+            size,
+            ..Evaluation::new(node, document)
         }
     }
 
@@ -25,12 +69,17 @@ impl<'a> Evaluation<'a> {
         &self.document.root
     }
 
-    pub fn find_nodes(
-        &self,
-        context: &AxisName,
-        node_test: &dyn NodeTest,
-        prev_step_axis: Option<AxisName>,
-    ) -> Nodeset {
+    /// The functions registered on the document, callable in queries.
+    pub fn functions(&self) -> &'a FunctionLibrary {
+        &self.document.functions
+    }
+
+    /// The value bound to `$name` on the document.
+    pub fn variable(&self, name: &str) -> Option<&'a Value> {
+        self.document.variables.get(name)
+    }
+
+    pub fn find_nodes(&self, context: &AxisName, node_test: &dyn NodeTest) -> Nodeset {
         let mut nodeset = Nodeset::new();
 
         match context {
@@ -38,13 +87,13 @@ impl<'a> Evaluation<'a> {
                 if let Some(parent) = self.node.parent() {
                     let eval = self.new_evaluation_from(&parent);
                     node_test.test(&eval, &mut nodeset);
-                    eval.find_nodes(&AxisName::Ancestor, node_test, prev_step_axis);
+                    nodeset.extend(eval.find_nodes(&AxisName::Ancestor, node_test));
                 }
             }
 
             AxisName::AncestorOrSelf => {
-                nodeset.extend(self.find_nodes(&AxisName::SelfAxis, node_test, prev_step_axis));
-                nodeset.extend(self.find_nodes(&AxisName::Ancestor, node_test, prev_step_axis));
+                nodeset.extend(self.find_nodes(&AxisName::SelfAxis, node_test));
+                nodeset.extend(self.find_nodes(&AxisName::Ancestor, node_test));
             }
 
             AxisName::Attribute => {
@@ -58,16 +107,9 @@ impl<'a> Evaluation<'a> {
             }
 
             AxisName::Child => {
-                // If our previous step was DescendantOrSelf that means we're going through all its' children
-                // so we'll just check out the current node to ensure it doesn't return nodes out of order.
-                if prev_step_axis == Some(AxisName::DescendantOrSelf) {
-                    let new_context = self.new_evaluation_from(self.node);
+                for child in self.node.children() {
+                    let new_context = self.new_evaluation_from(&child);
                     node_test.test(&new_context, &mut nodeset);
-                } else {
-                    for child in self.node.children() {
-                        let new_context = self.new_evaluation_from(&child);
-                        node_test.test(&new_context, &mut nodeset);
-                    }
                 }
             }
 
@@ -77,17 +119,13 @@ impl<'a> Evaluation<'a> {
 
                     node_test.test(&new_context, &mut nodeset);
 
-                    nodeset.extend(new_context.find_nodes(
-                        &AxisName::Descendant,
-                        node_test,
-                        prev_step_axis,
-                    ));
+                    nodeset.extend(new_context.find_nodes(&AxisName::Descendant, node_test));
                 }
             }
 
             AxisName::DescendantOrSelf => {
-                nodeset.extend(self.find_nodes(&AxisName::SelfAxis, node_test, prev_step_axis));
-                nodeset.extend(self.find_nodes(&AxisName::Descendant, node_test, prev_step_axis));
+                nodeset.extend(self.find_nodes(&AxisName::SelfAxis, node_test));
+                nodeset.extend(self.find_nodes(&AxisName::Descendant, node_test));
             }
 
             // excluding any descendants and excluding attribute nodes and namespace nodes
@@ -96,32 +134,27 @@ impl<'a> Evaluation<'a> {
                 value::following_nodes_from_parent(self.node)
                     .into_iter()
                     .for_each(|node| {
-                        nodeset.extend(self.new_evaluation_from(&node).find_nodes(
-                            &AxisName::DescendantOrSelf,
-                            node_test,
-                            prev_step_axis,
-                        ))
+                        nodeset.extend(
+                            self.new_evaluation_from(&node)
+                                .find_nodes(&AxisName::DescendantOrSelf, node_test),
+                        )
                     });
 
                 // Get the parents children after 'self.node.parent()'
                 if let Some(parent) = self.node.parent() {
-                    nodeset.extend(self.new_evaluation_from(&parent).find_nodes(
-                        &AxisName::Following,
-                        node_test,
-                        prev_step_axis,
-                    ));
+                    nodeset.extend(
+                        self.new_evaluation_from(&parent)
+                            .find_nodes(&AxisName::Following, node_test),
+                    );
                 }
             }
 
             // if the context node is an attribute node or namespace node, the following-sibling axis is empty
             AxisName::FollowingSibling => {
                 // Returns children in current parent after 'self.node'.
-                nodeset.extend(
-                    value::following_nodes_from_parent(self.node)
-                        .into_iter()
-                        .collect::<Vec<Node>>()
-                        .into(),
-                );
+                for sibling in self.node.following_siblings() {
+                    node_test.test(&self.new_evaluation_from(&sibling), &mut nodeset);
+                }
             }
 
             // contains the namespace nodes of the context node;
@@ -142,32 +175,27 @@ impl<'a> Evaluation<'a> {
                 value::preceding_nodes_from_parent(self.node)
                     .into_iter()
                     .for_each(|node| {
-                        nodeset.extend(self.new_evaluation_from(&node).find_nodes(
-                            &AxisName::DescendantOrSelf,
-                            node_test,
-                            prev_step_axis,
-                        ))
+                        nodeset.extend(
+                            self.new_evaluation_from(&node)
+                                .find_nodes(&AxisName::DescendantOrSelf, node_test),
+                        )
                     });
 
                 // Get the parents children before 'self.node.parent()'
                 if let Some(parent) = self.node.parent() {
-                    nodeset.extend(self.new_evaluation_from(&parent).find_nodes(
-                        &AxisName::Preceding,
-                        node_test,
-                        prev_step_axis,
-                    ));
+                    nodeset.extend(
+                        self.new_evaluation_from(&parent)
+                            .find_nodes(&AxisName::Preceding, node_test),
+                    );
                 }
             }
 
             // if the context node is an attribute node or namespace node, the preceding-sibling axis is empty
             AxisName::PrecedingSibling => {
                 // Returns children in current parent before 'self.node'.
-                nodeset.extend(
-                    value::preceding_nodes_from_parent(self.node)
-                        .into_iter()
-                        .collect::<Vec<Node>>()
-                        .into(),
-                );
+                for sibling in self.node.preceding_siblings() {
+                    node_test.test(&self.new_evaluation_from(&sibling), &mut nodeset);
+                }
             }
 
             AxisName::SelfAxis => {
@@ -178,12 +206,89 @@ impl<'a> Evaluation<'a> {
         nodeset
     }
 
+    /// Finds the first node the axis would return without searching for the rest.
+    pub fn find_first(&self, context: &AxisName, node_test: &dyn NodeTest) -> Option<Node> {
+        self.find_limited(context, node_test, 1).into_iter().next()
+    }
+
+    /// Finds the first `limit` nodes the axis would return without searching for the rest.
+    ///
+    /// Only the child, descendant and self axes stop early, the others are fully searched.
+    pub fn find_limited(
+        &self,
+        context: &AxisName,
+        node_test: &dyn NodeTest,
+        limit: usize,
+    ) -> Nodeset {
+        let mut nodeset = Nodeset::new();
+
+        self.push_limited(context, node_test, limit, &mut nodeset);
+
+        nodeset.nodes.truncate(limit);
+
+        nodeset
+    }
+
+    fn push_limited(
+        &self,
+        context: &AxisName,
+        node_test: &dyn NodeTest,
+        limit: usize,
+        nodeset: &mut Nodeset,
+    ) {
+        match context {
+            AxisName::Child => {
+                for child in self.node.children_iter() {
+                    if nodeset.len() >= limit {
+                        break;
+                    }
+
+                    node_test.test(&self.new_evaluation_from(&child), nodeset);
+                }
+            }
+
+            AxisName::Descendant => {
+                for child in self.node.children_iter() {
+                    if nodeset.len() >= limit {
+                        break;
+                    }
+
+                    let new_context = self.new_evaluation_from(&child);
+
+                    node_test.test(&new_context, nodeset);
+
+                    new_context.push_limited(&AxisName::Descendant, node_test, limit, nodeset);
+                }
+            }
+
+            AxisName::DescendantOrSelf => {
+                self.push_limited(&AxisName::SelfAxis, node_test, limit, nodeset);
+                self.push_limited(&AxisName::Descendant, node_test, limit, nodeset);
+            }
+
+            AxisName::SelfAxis => {
+                if nodeset.len() < limit {
+                    node_test.test(self, nodeset);
+                }
+            }
+
+            _ => {
+                // Reverse axes find the nearest nodes first, the limit is on document order.
+                let mut found = self.find_nodes(context, node_test);
+                found.sort_document_order();
+                nodeset.extend(found);
+            }
+        }
+    }
+
     pub fn new_evaluation_from(&'a self, node: &'a Node) -> Self {
         Self {
             document: self.document,
             node,
             position: 1,
             size: 1,
+            options: self.options,
+            id: next_id(),
         }
     }
 }
@@ -21,9 +21,11 @@
 // Whitespace can be freely used between tokens.
 // The tokenization process is described in [3.7 Lexical Structure].
 
+use std::collections::HashSet;
 use std::fmt;
-use std::sync::Mutex;
+use std::rc::Rc;
 
+use markup5ever_rcdom::Node as DomNode;
 use tracing::{trace, Level};
 
 use crate::functions::{self, Args};
@@ -64,6 +66,38 @@ pub trait Expression: fmt::Debug {
 
         Ok(nodes)
     }
+
+    /// Does the expression produce a value. Node-set expressions can stop at the first node found.
+    fn exists(&mut self, eval: &Evaluation) -> Result<bool> {
+        Ok(self.next_eval(eval)?.is_some())
+    }
+
+    /// Up to `limit` of the produced nodes. Node-set expressions can stop searching once they're found.
+    fn take_nodes(&mut self, eval: &Evaluation, limit: usize) -> Result<Vec<Node>> {
+        let mut nodes = Vec::new();
+
+        while nodes.len() < limit {
+            let Some(value) = self.next_eval(eval)? else {
+                break;
+            };
+
+            nodes.push(value.into_node()?);
+        }
+
+        Ok(nodes)
+    }
+
+    /// Does the expression only ever produce nodes.
+    fn is_nodeset(&self) -> bool {
+        false
+    }
+
+    /// Is the value the same for every node of a node-set, only depending on its size if anything. ex: 'last() - 1'
+    ///
+    /// A predicate like this is evaluated once rather than for each node.
+    fn only_uses_context_size(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug)]
@@ -84,9 +118,13 @@ impl Expression for Addition {
         let right_value = res_opt_def_NAN!(self.right.next_eval(eval));
 
         Ok(Some(Value::Number(
-            left_value.number()? + right_value.number()?,
+            left_value.convert_to_number()? + right_value.convert_to_number()?,
         )))
     }
+
+    fn only_uses_context_size(&self) -> bool {
+        self.left.only_uses_context_size() && self.right.only_uses_context_size()
+    }
 }
 
 #[derive(Debug)]
@@ -107,9 +145,96 @@ impl Expression for Subtraction {
         let right_value = res_opt_def_NAN!(self.right.next_eval(eval));
 
         Ok(Some(Value::Number(
-            left_value.number()? - right_value.number()?,
+            left_value.convert_to_number()? - right_value.convert_to_number()?,
         )))
     }
+
+    fn only_uses_context_size(&self) -> bool {
+        self.left.only_uses_context_size() && self.right.only_uses_context_size()
+    }
+}
+
+#[derive(Debug)]
+pub struct Multiplication {
+    left: ExpressionArg,
+    right: ExpressionArg,
+}
+
+impl Multiplication {
+    pub fn new(left: ExpressionArg, right: ExpressionArg) -> Self {
+        Self { left, right }
+    }
+}
+
+impl Expression for Multiplication {
+    fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
+        let left_value = res_opt_def_NAN!(self.left.next_eval(eval));
+        let right_value = res_opt_def_NAN!(self.right.next_eval(eval));
+
+        Ok(Some(Value::Number(
+            left_value.convert_to_number()? * right_value.convert_to_number()?,
+        )))
+    }
+
+    fn only_uses_context_size(&self) -> bool {
+        self.left.only_uses_context_size() && self.right.only_uses_context_size()
+    }
+}
+
+// Follows IEEE 754. ex: '1 div 0' is Infinity and '0 div 0' is NaN
+#[derive(Debug)]
+pub struct Division {
+    left: ExpressionArg,
+    right: ExpressionArg,
+}
+
+impl Division {
+    pub fn new(left: ExpressionArg, right: ExpressionArg) -> Self {
+        Self { left, right }
+    }
+}
+
+impl Expression for Division {
+    fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
+        let left_value = res_opt_def_NAN!(self.left.next_eval(eval));
+        let right_value = res_opt_def_NAN!(self.right.next_eval(eval));
+
+        Ok(Some(Value::Number(
+            left_value.convert_to_number()? / right_value.convert_to_number()?,
+        )))
+    }
+
+    fn only_uses_context_size(&self) -> bool {
+        self.left.only_uses_context_size() && self.right.only_uses_context_size()
+    }
+}
+
+// The remainder of a truncating division. ex: '-5 mod 2' is -1
+#[derive(Debug)]
+pub struct Modulo {
+    left: ExpressionArg,
+    right: ExpressionArg,
+}
+
+impl Modulo {
+    pub fn new(left: ExpressionArg, right: ExpressionArg) -> Self {
+        Self { left, right }
+    }
+}
+
+impl Expression for Modulo {
+    fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
+        let left_value = res_opt_def_NAN!(self.left.next_eval(eval));
+        let right_value = res_opt_def_NAN!(self.right.next_eval(eval));
+
+        Ok(Some(Value::Number(
+            left_value.convert_to_number()? % right_value.convert_to_number()?,
+        )))
+    }
+
+    fn only_uses_context_size(&self) -> bool {
+        self.left.only_uses_context_size() && self.right.only_uses_context_size()
+    }
 }
 
 #[derive(Debug)]
@@ -220,10 +345,11 @@ impl Equal {
 
 impl Expression for Equal {
     fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
-        let left_value = res_opt_def_false!(self.left.next_eval(eval));
-        let right_value = res_opt_def_false!(self.right.next_eval(eval));
+        let value = compare_values(&mut self.left, &mut self.right, eval, |left, right| {
+            left.equals(right)
+        })?;
 
-        Ok(Some(Value::Boolean(left_value == right_value)))
+        Ok(Some(Value::Boolean(value)))
     }
 }
 
@@ -241,13 +367,93 @@ impl NotEqual {
 
 impl Expression for NotEqual {
     fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
-        let left_value = res_opt_def_false!(self.left.next_eval(eval));
-        let right_value = res_opt_def_false!(self.right.next_eval(eval));
+        let value = compare_values(&mut self.left, &mut self.right, eval, |left, right| {
+            !left.equals(right)
+        })?;
 
-        Ok(Some(Value::Boolean(left_value != right_value)))
+        Ok(Some(Value::Boolean(value)))
     }
 }
 
+// When either side is a boolean the other is converted with boolean() and they're compared once.
+// Otherwise a node-set is compared node by node, the comparison is true if it's true for any of them.
+// An empty node-set makes it false.
+fn compare_values(
+    left: &mut ExpressionArg,
+    right: &mut ExpressionArg,
+    eval: &Evaluation,
+    compare: fn(&Value, &Value) -> bool,
+) -> Result<bool> {
+    let left_values = produced_values(left, eval)?;
+    let right_values = produced_values(right, eval)?;
+
+    let is_boolean = |values: &[Value]| matches!(values, [Value::Boolean(_)]);
+
+    if is_boolean(&left_values) || is_boolean(&right_values) {
+        return Ok(compare(
+            &Value::Boolean(values_to_boolean(left_values)),
+            &Value::Boolean(values_to_boolean(right_values)),
+        ));
+    }
+
+    let left_values = comparison_values(left_values);
+    let right_values = comparison_values(right_values);
+
+    Ok(left_values
+        .iter()
+        .any(|left| right_values.iter().any(|right| compare(left, right))))
+}
+
+// A node-set is true if it produced any nodes, a single value uses the boolean() rules.
+fn values_to_boolean(values: Vec<Value>) -> bool {
+    values
+        .into_iter()
+        .next()
+        .is_some_and(Value::convert_to_boolean)
+}
+
+// Node-sets split into their nodes.
+fn comparison_values(values: Vec<Value>) -> Vec<Value> {
+    values
+        .into_iter()
+        .flat_map(|value| match value {
+            Value::Nodeset(set) => set.nodes.into_iter().map(Value::Node).collect::<Vec<_>>(),
+            value => vec![value],
+        })
+        .collect()
+}
+
+// The first value, followed by the rest of them if the expression is producing a node-set.
+pub(crate) fn produced_values(expr: &mut ExpressionArg, eval: &Evaluation) -> Result<Vec<Value>> {
+    let Some(first) = expr.next_eval(eval)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut values = vec![first];
+
+    if expr.is_nodeset() {
+        while let Some(value) = expr.next_eval(eval)? {
+            values.push(value);
+        }
+    }
+
+    Ok(values)
+}
+
+// Every produced node. Anything other than a node is an error.
+fn produced_nodes(expr: &mut ExpressionArg, eval: &Evaluation) -> Result<Nodeset> {
+    let mut nodes = Nodeset::new();
+
+    for value in produced_values(expr, eval)? {
+        match value {
+            Value::Nodeset(set) => nodes.extend(set),
+            value => nodes.add_node(value.into_node()?),
+        }
+    }
+
+    Ok(nodes)
+}
+
 #[derive(Debug)]
 pub struct And {
     left: ExpressionArg,
@@ -262,12 +468,11 @@ impl And {
 
 impl Expression for And {
     fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
-        let left_value = res_opt_def_false!(self.left.next_eval(eval));
-        let right_value = res_opt_def_false!(self.right.next_eval(eval));
+        // The right side isn't evaluated when the left is false.
+        let value =
+            boolean_operand(&mut self.left, eval)? && boolean_operand(&mut self.right, eval)?;
 
-        Ok(Some(Value::Boolean(
-            left_value.boolean()? && right_value.boolean()?,
-        )))
+        Ok(Some(Value::Boolean(value)))
     }
 }
 
@@ -285,21 +490,51 @@ impl Or {
 
 impl Expression for Or {
     fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
-        let left_value = res_opt_def_false!(self.left.next_eval(eval));
-        let right_value = res_opt_def_false!(self.right.next_eval(eval));
+        // The right side isn't evaluated when the left is true.
+        let value =
+            boolean_operand(&mut self.left, eval)? || boolean_operand(&mut self.right, eval)?;
 
-        Ok(Some(Value::Boolean(
-            left_value.boolean()? || right_value.boolean()?,
-        )))
+        Ok(Some(Value::Boolean(value)))
+    }
+}
+
+// A node-set is true if it isn't empty, which only needs the first node. Other values use the boolean() rules.
+fn boolean_operand(expr: &mut ExpressionArg, eval: &Evaluation) -> Result<bool> {
+    if expr.is_nodeset() {
+        expr.exists(eval)
+    } else {
+        Ok(expr.next_eval(eval)?.is_some_and(Value::convert_to_boolean))
+    }
+}
+
+// Expressions inside a predicate are re-used for each node it checks, and a node can be checked more than once.
+// ex: the div following both p of '//p/following::div[span]'
+// Nodes found for a previous evaluation, `cached_for`, are dropped once a new one starts.
+fn reset_on_new_context<T>(found: &mut Option<T>, cached_for: &Option<usize>, eval: &Evaluation) {
+    if *cached_for != Some(eval.id()) {
+        *found = None;
     }
 }
 
+// The next of the found nodes. Once they've all been returned they're found again the next time it's evaluated.
+fn next_found(found: &mut Option<Vec<Node>>) -> Option<Value> {
+    let next = found.as_mut().and_then(|nodes| nodes.pop());
+
+    if next.is_none() {
+        *found = None;
+    }
+
+    next.map(Value::Node)
+}
+
 // Primary Expressions
 #[derive(Debug)]
 pub struct Union {
     left: ExpressionArg,
     right: ExpressionArg,
-    skip_left: Mutex<bool>,
+
+    found_cache: Option<Vec<Node>>,
+    cached_for: Option<usize>,
 }
 
 impl Union {
@@ -307,30 +542,97 @@ impl Union {
         Self {
             left,
             right,
-            skip_left: Mutex::new(false),
+            found_cache: None,
+            cached_for: None,
         }
     }
 }
 
 impl Expression for Union {
     fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
-        if !*self.skip_left.lock().unwrap() {
-            *self.skip_left.lock().unwrap() = true;
+        reset_on_new_context(&mut self.found_cache, &self.cached_for, eval);
 
-            let left_value = self.left.next_eval(eval)?;
+        if self.found_cache.is_none() {
+            self.cached_for = Some(eval.id());
 
-            if left_value.is_some() {
-                return Ok(left_value);
+            let mut nodes = Nodeset::new();
+
+            for value in self.left.collect(eval)? {
+                nodes.add_node(value.into_node()?);
+            }
+
+            for value in self.right.collect(eval)? {
+                nodes.add_node(value.into_node()?);
             }
+
+            // Either side can find the same node. Sorting places them next to each other.
+            nodes.sort_document_order();
+            nodes.nodes.dedup_by(|a, b| a.same_node(b));
+
+            // Reverse it so we can use .pop later.
+            nodes.nodes.reverse();
+
+            self.found_cache = Some(nodes.nodes);
+        }
+
+        Ok(next_found(&mut self.found_cache))
+    }
+
+    fn is_nodeset(&self) -> bool {
+        true
+    }
+}
+
+/// Predicates on a primary expression. ex: `(//a)[1]`
+///
+/// Unlike a step's predicates, positions count through the whole node-set in document order.
+#[derive(Debug)]
+pub struct Filter {
+    expr: ExpressionArg,
+    predicates: Vec<Predicate>,
+
+    found_cache: Option<Vec<Node>>,
+    cached_for: Option<usize>,
+}
+
+impl Filter {
+    pub fn new(expr: ExpressionArg, predicates: Vec<ExpressionArg>) -> Self {
+        Self {
+            expr,
+            predicates: predicates.into_iter().map(Predicate).collect(),
+            found_cache: None,
+            cached_for: None,
         }
+    }
+}
+
+impl Expression for Filter {
+    fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
+        reset_on_new_context(&mut self.found_cache, &self.cached_for, eval);
 
-        let right_value = self.right.next_eval(eval)?;
+        if self.found_cache.is_none() {
+            self.cached_for = Some(eval.id());
+
+            let mut nodes = produced_nodes(&mut self.expr, eval)?;
+
+            nodes.sort_document_order();
+            nodes.nodes.dedup_by(|a, b| a.same_node(b));
+
+            for predicate in &mut self.predicates {
+                nodes = predicate.select(eval, nodes)?;
+            }
+
+            // Reverse it so we can use .pop later.
+            nodes.nodes.reverse();
 
-        if right_value.is_some() {
-            return Ok(right_value);
+            self.found_cache = Some(nodes.nodes);
         }
 
-        Ok(None)
+        Ok(next_found(&mut self.found_cache))
+    }
+
+    fn is_nodeset(&self) -> bool {
+        true
     }
 }
 
@@ -347,6 +649,69 @@ impl Expression for Literal {
     fn next_eval(&mut self, _: &Evaluation) -> Result<Option<Value>> {
         Ok(Some(self.0.clone()))
     }
+
+    fn only_uses_context_size(&self) -> bool {
+        true
+    }
+}
+
+/// A `$name` reference. A node-set value is produced one node at a time in document order.
+#[derive(Debug)]
+pub struct Variable {
+    value: Value,
+
+    found: Option<std::vec::IntoIter<Node>>,
+    cached_for: Option<usize>,
+}
+
+impl Variable {
+    pub fn new(value: Value) -> Self {
+        let value = match value {
+            Value::Node(node) => Value::Nodeset(Nodeset { nodes: vec![node] }),
+            Value::Nodeset(mut set) => {
+                set.sort_document_order();
+                Value::Nodeset(set)
+            }
+            value => value,
+        };
+
+        Self {
+            value,
+            found: None,
+            cached_for: None,
+        }
+    }
+}
+
+impl Expression for Variable {
+    fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
+        let Value::Nodeset(set) = &self.value else {
+            return Ok(Some(self.value.clone()));
+        };
+
+        // A predicate may only take the first node before checking the next node.
+        reset_on_new_context(&mut self.found, &self.cached_for, eval);
+
+        if self.found.is_none() {
+            self.cached_for = Some(eval.id());
+        }
+
+        let nodes = self
+            .found
+            .get_or_insert_with(|| set.nodes.clone().into_iter());
+        let next = nodes.next();
+
+        // Start over the next time it's evaluated.
+        if next.is_none() {
+            self.found = None;
+        }
+
+        Ok(next.map(Value::Node))
+    }
+
+    fn is_nodeset(&self) -> bool {
+        self.value.is_nodeset()
+    }
 }
 
 // Nodeset
@@ -376,47 +741,67 @@ pub struct Path {
 
     // TODO: We just cache everything it validated. Later we'll make it more ergonomic.
     found_cache: Option<Vec<Node>>,
-    cached_from: Option<Node>,
+    cached_for: Option<usize>,
 }
 
 impl Path {
     pub fn new(start_pos: ExpressionArg, steps: Vec<Step>) -> Self {
         Self {
             start_pos,
-            steps,
+            steps: collapse_descendant_steps(steps),
             found_cache: None,
-            cached_from: None,
+            cached_for: None,
         }
     }
 }
 
+// 'descendant-or-self::node()/child::x' (what '//x' expands to) selects the same nodes as 'descendant::x'
+// as long as neither step has predicates. A predicate on the child step is relative to each parent so it must stay.
+fn collapse_descendant_steps(steps: Vec<Step>) -> Vec<Step> {
+    let mut collapsed: Vec<Step> = Vec::with_capacity(steps.len());
+
+    for step in steps {
+        if step.axis == AxisName::Child && step.predicates.is_empty() {
+            if let Some(prev) = collapsed.last_mut() {
+                if prev.axis == AxisName::DescendantOrSelf
+                    && prev.predicates.is_empty()
+                    && prev.node_test.is_any_node()
+                {
+                    prev.axis = AxisName::Descendant;
+                    prev.node_test = step.node_test;
+                    continue;
+                }
+            }
+        }
+
+        collapsed.push(step);
+    }
+
+    collapsed
+}
+
 impl Expression for Path {
     fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
-        // TODO: Better way to handle this.
-        // Needed for Predicate Function Path. They're re-used for each node check.
-        if self.cached_from.as_ref() != Some(eval.node) {
-            self.found_cache = None;
-        }
+        reset_on_new_context(&mut self.found_cache, &self.cached_for, eval);
 
         if self.found_cache.is_none() {
-            self.cached_from = Some(eval.node.clone());
+            self.cached_for = Some(eval.id());
 
             trace!("VVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVVV");
 
-            let Some(result) = self.start_pos.next_eval(eval)? else {
-				return Ok(None);
-			};
+            let Some(mut nodes) = self.start_nodes(eval)? else {
+                return Ok(None);
+            };
 
-            let node = result.into_node()?;
-
-            trace!("-> {}", crate::compile_lines(&node));
-
-            let mut nodes = Nodeset { nodes: vec![node] };
+            if tracing::enabled!(Level::TRACE) {
+                nodes
+                    .nodes
+                    .iter()
+                    .for_each(|node| trace!("-> {}", crate::compile_lines(node)));
+            }
 
-            let mut prev_step_axis = None;
             for (i, step) in self.steps.iter_mut().enumerate() {
-                nodes = step.evaluate(eval, nodes, prev_step_axis)?;
-                prev_step_axis = Some(step.axis);
+                nodes = step.evaluate(eval, nodes)?;
 
                 if tracing::enabled!(Level::TRACE) {
                     trace!("Step [{i}]");
@@ -436,9 +821,69 @@ impl Expression for Path {
             self.found_cache = Some(nodes.nodes);
         }
 
-        let nodes = self.found_cache.as_mut().unwrap();
+        Ok(next_found(&mut self.found_cache))
+    }
+
+    fn exists(&mut self, eval: &Evaluation) -> Result<bool> {
+        match self.evaluate_leading_steps(eval)? {
+            Some((nodes, Some(last))) => last.exists(eval, nodes),
+            Some((nodes, None)) => Ok(!nodes.is_empty()),
+            None => Ok(false),
+        }
+    }
+
+    fn take_nodes(&mut self, eval: &Evaluation, limit: usize) -> Result<Vec<Node>> {
+        // Already being iterated by this evaluation, continue from there.
+        if let Some(nodes) = self
+            .found_cache
+            .as_mut()
+            .filter(|_| self.cached_for == Some(eval.id()))
+        {
+            let rest = nodes.len().saturating_sub(limit);
+
+            return Ok(nodes.drain(rest..).rev().collect());
+        }
+
+        let nodes = match self.evaluate_leading_steps(eval)? {
+            Some((nodes, Some(last))) => last.evaluate_limited(eval, nodes, limit)?,
+            Some((nodes, None)) => nodes,
+            None => return Ok(Vec::new()),
+        };
 
-        Ok(nodes.pop().map(Value::Node))
+        Ok(nodes.nodes.into_iter().take(limit).collect())
+    }
+
+    fn is_nodeset(&self) -> bool {
+        true
+    }
+}
+
+impl Path {
+    // The nodes the first step starts from. A node-set, such as one returned by a function, starts from each node.
+    fn start_nodes(&mut self, eval: &Evaluation) -> Result<Option<Nodeset>> {
+        let nodes = produced_nodes(&mut self.start_pos, eval)?;
+
+        Ok((!nodes.is_empty()).then_some(nodes))
+    }
+
+    // Evaluates every step except the last, which is returned alongside the nodes it starts from.
+    fn evaluate_leading_steps(
+        &mut self,
+        eval: &Evaluation,
+    ) -> Result<Option<(Nodeset, Option<&mut Step>)>> {
+        let Some(mut nodes) = self.start_nodes(eval)? else {
+            return Ok(None);
+        };
+
+        let Some((last, steps)) = self.steps.split_last_mut() else {
+            return Ok(Some((nodes, None)));
+        };
+
+        for step in steps {
+            nodes = step.evaluate(eval, nodes)?;
+        }
+
+        Ok(Some((nodes, Some(last))))
     }
 }
 
@@ -464,26 +909,60 @@ impl Step {
         }
     }
 
-    fn evaluate(
+    pub(crate) fn evaluate(
         &mut self,
         context: &Evaluation,
         starting_nodes: Nodeset,
-        prev_step_axis: Option<AxisName>,
     ) -> Result<Nodeset> {
+        let starting_count = starting_nodes.len();
+
+        // Without predicates a descendant search from inside an already searched subtree can only find the same nodes.
+        let skip_searched = self.predicates.is_empty()
+            && matches!(self.axis, AxisName::Descendant | AxisName::DescendantOrSelf);
+
+        let mut searched = HashSet::new();
+
         let mut unique = Nodeset::new();
 
         for node in starting_nodes {
+            if skip_searched && !node.is_attribute() && !node.is_namespace() {
+                if is_within_searched(&node, &searched) {
+                    continue;
+                }
+
+                if let Some(handle) = node.handle() {
+                    searched.insert(Rc::as_ptr(&handle));
+                }
+            }
+
             let child_context = context.new_evaluation_from(&node);
-            let mut nodes =
-                child_context.find_nodes(&self.axis, self.node_test.as_ref(), prev_step_axis);
+            let mut nodes = child_context.find_nodes(&self.axis, self.node_test.as_ref());
+
+            // Predicate positions count along the axis. ex: 'ancestor::div[1]' is the nearest div
+            if self.axis.is_reverse() {
+                nodes.sort_document_order();
+                nodes.nodes.reverse();
+            }
 
             for predicate in &mut self.predicates {
                 nodes = predicate.select(context, nodes)?;
             }
 
+            // Back into document order.
+            if self.axis.is_reverse() {
+                nodes.nodes.reverse();
+            }
+
             unique.extend(nodes);
         }
 
+        // Nodes found from separate starting nodes are grouped by them, not by document order.
+        // Sorting also places any node found more than once next to itself.
+        if starting_count > 1 {
+            unique.sort_document_order();
+            unique.nodes.dedup_by(|a, b| a.same_node(b));
+        }
+
         if !self.predicates.is_empty() {
             trace!("Pre Predicate:");
             trace!("{:#?}", unique);
@@ -491,6 +970,79 @@ impl Step {
 
         Ok(unique)
     }
+
+    /// The first `limit` nodes the step finds from the starting nodes, without searching for the rest.
+    pub(crate) fn evaluate_limited(
+        &mut self,
+        context: &Evaluation,
+        starting_nodes: Nodeset,
+        limit: usize,
+    ) -> Result<Nodeset> {
+        // A predicate can depend on the position within every node found.
+        if !self.predicates.is_empty() {
+            let mut nodes = self.evaluate(context, starting_nodes)?;
+            nodes.nodes.truncate(limit);
+            return Ok(nodes);
+        }
+
+        let starting_count = starting_nodes.len();
+
+        let mut found = Nodeset::new();
+
+        // Each starting node's nodes are in document order so the first `limit` overall are within their first `limit`.
+        for node in starting_nodes {
+            let child_context = context.new_evaluation_from(&node);
+            found.extend(child_context.find_limited(&self.axis, self.node_test.as_ref(), limit));
+        }
+
+        if starting_count > 1 {
+            found.sort_document_order();
+            found.nodes.dedup_by(|a, b| a.same_node(b));
+        }
+
+        found.nodes.truncate(limit);
+
+        Ok(found)
+    }
+
+    /// Does the step find any node from the starting nodes. Stops searching at the first one found.
+    pub(crate) fn exists(&mut self, context: &Evaluation, starting_nodes: Nodeset) -> Result<bool> {
+        // A predicate can depend on the position within every node found.
+        if !self.predicates.is_empty() {
+            return Ok(!self.evaluate(context, starting_nodes)?.is_empty());
+        }
+
+        for node in starting_nodes {
+            let child_context = context.new_evaluation_from(&node);
+
+            if child_context
+                .find_first(&self.axis, self.node_test.as_ref())
+                .is_some()
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+// Is the node, or one of its ancestors, a starting node which was already searched.
+fn is_within_searched(node: &Node, searched: &HashSet<*const DomNode>) -> bool {
+    let mut current = Some(node.clone());
+
+    while let Some(node) = current {
+        if node
+            .handle()
+            .is_some_and(|handle| searched.contains(&Rc::as_ptr(&handle)))
+        {
+            return true;
+        }
+
+        current = node.parent();
+    }
+
+    false
 }
 
 // https://www.w3.org/TR/1999/REC-xpath-19991116/#predicates
@@ -501,6 +1053,10 @@ impl Predicate {
     fn select(&mut self, context: &Evaluation<'_>, nodes: Nodeset) -> Result<Nodeset> {
         let node_count = nodes.len();
 
+        if self.0.only_uses_context_size() {
+            return self.select_once(context, nodes);
+        }
+
         let mut found = Vec::new();
 
         for (index, node) in nodes.into_iter().enumerate() {
@@ -519,14 +1075,42 @@ impl Predicate {
         Ok(found.into())
     }
 
+    // The same value for every node. ex: '[last()]' or '[2]'
+    fn select_once(&mut self, context: &Evaluation<'_>, nodes: Nodeset) -> Result<Nodeset> {
+        let Some(first) = nodes.nodes.first() else {
+            return Ok(nodes);
+        };
+
+        let mut ctx = context.new_evaluation_from(first);
+        ctx.size = nodes.len();
+
+        Ok(match self.0.next_eval(&ctx)? {
+            Some(Value::Number(v)) => nodes
+                .into_iter()
+                .enumerate()
+                .filter(|&(index, _)| v == (index + 1) as f64)
+                .map(|(_, node)| node)
+                .collect::<Vec<_>>()
+                .into(),
+            Some(value) if value.is_something() => nodes,
+            _ => Nodeset::new(),
+        })
+    }
+
     fn matches_eval(&mut self, eval: &Evaluation<'_>) -> Result<Option<bool>> {
+        // Only needs to know that a node exists. ex: //div[a]
+        if self.0.is_nodeset() {
+            return Ok(Some(self.0.exists(eval)?));
+        }
+
         let Some(value) = self.0.next_eval(eval)? else {
 			return Ok(None);
 		};
 
         Ok(Some(match value {
             // Is Node in the correct position? ex: //node[3]
-            Value::Number(v) => eval.position == v as usize,
+            // Compared exactly, a position which isn't a whole number never matches.
+            Value::Number(v) => v == eval.position as f64,
             // Otherwise ensure a value properly exists.
             _ => value.is_something(),
         }))
@@ -534,17 +1118,45 @@ impl Predicate {
 }
 
 #[derive(Debug)]
-pub struct Function(Box<dyn functions::Function>, Vec<ExpressionArg>);
+pub struct Function(
+    Box<dyn functions::Function>,
+    Vec<ExpressionArg>,
+    Option<std::vec::IntoIter<Node>>,
+    // The evaluation the node-set was returned for.
+    Option<usize>,
+);
 
 impl Function {
     pub fn new(inner: Box<dyn functions::Function>, args: Vec<ExpressionArg>) -> Function {
-        Self(inner, args)
+        Self(inner, args, None, None)
     }
 }
 
 impl Expression for Function {
     fn next_eval(&mut self, eval: &Evaluation) -> Result<Option<Value>> {
-        self.0.exec(eval, Args::new(self.1.as_mut())).map(Some)
+        reset_on_new_context(&mut self.2, &self.3, eval);
+
+        // A returned node-set is produced one node at a time in document order, like a path.
+        if let Some(nodes) = self.2.as_mut() {
+            let next = nodes.next();
+
+            if next.is_none() {
+                self.2 = None;
+            }
+
+            return Ok(next.map(Value::Node));
+        }
+
+        match self.0.exec(eval, Args::new(self.1.as_mut()))? {
+            Value::Nodeset(mut set) => {
+                set.sort_document_order();
+                self.2 = Some(set.into_iter());
+                self.3 = Some(eval.id());
+                self.next_eval(eval)
+            }
+
+            value => Ok(Some(value)),
+        }
 
         // TODO: Can't get type_name of dyn Functions' struct.
         // match self.0.exec(eval, Args::new(self.1.as_mut())) {
@@ -558,4 +1170,13 @@ impl Expression for Function {
         // 	}
         // }
     }
+
+    // Only known once it's returned a node-set, which is then produced until it runs out.
+    fn is_nodeset(&self) -> bool {
+        self.2.is_some()
+    }
+
+    fn only_uses_context_size(&self) -> bool {
+        self.0.only_uses_context_size() && self.1.iter().all(|arg| arg.only_uses_context_size())
+    }
 }
@@ -1,13 +1,16 @@
+use std::collections::{HashMap, HashSet};
 use std::iter::Peekable;
 
 use tracing::{error, trace, Level};
 
 use crate::expressions::*;
-use crate::functions;
+use crate::functions::{self, FunctionLibrary};
 use crate::nodetest;
+use crate::span::SourceSpans;
+use crate::value;
 use crate::{
-    AxisName, Error, Evaluation, ExprToken, Node, NodeTest, NodeType, Nodeset, Operator,
-    PrincipalNodeType, Result, Tokenizer, Value,
+    AxisName, Error, Evaluation, EvaluationOptions, ExprToken, Node, NodeTest, NodeType, Nodeset,
+    Operator, PrincipalNodeType, Result, Tokenizer, Value,
 };
 
 type ExpressionResult = Result<Option<ExpressionArg>>;
@@ -15,39 +18,249 @@ type ExpressionResult = Result<Option<ExpressionArg>>;
 pub struct ProduceIter<'a> {
     eval: Evaluation<'a>,
     expr: ExpressionArg,
+    finished: bool,
 }
 
 impl<'a> ProduceIter<'a> {
-    pub fn collect_nodes(mut self) -> Result<Nodeset> {
-        self.try_fold::<_, _, Result<Nodeset>>(Nodeset::new(), |mut set, v| {
-            set.add_node(v?.into_node()?);
-            Ok(set)
+    /// Collects the produced nodes.
+    ///
+    /// Requires the query to produce nodes, a boolean, number or string result is an error.
+    /// Use [`Document::evaluate_all`] when the query may produce any value.
+    pub fn collect_nodes(self) -> Result<Nodeset> {
+        let mut set = Nodeset::new();
+        self.collect_into(&mut set)?;
+        Ok(set)
+    }
+
+    /// Appends the produced nodes to `set`, which can be reused across queries.
+    ///
+    /// The same requirements as [`ProduceIter::collect_nodes`]. Nodes produced before an error are kept.
+    pub fn collect_into(self, set: &mut Nodeset) -> Result<()> {
+        for value in self {
+            set.add_node(value?.into_node()?);
+        }
+
+        Ok(())
+    }
+
+    /// The string-value of each produced value, without duplicates. The first of each is kept in place.
+    pub fn unique_strings(self) -> Result<Vec<String>> {
+        let mut seen = HashSet::new();
+        let mut strings = Vec::new();
+
+        for value in self {
+            let value = value?.convert_to_string()?;
+
+            if seen.insert(value.clone()) {
+                strings.push(value);
+            }
+        }
+
+        Ok(strings)
+    }
+
+    /// Iterate the produced nodes. A non-node value is returned as an error item.
+    pub fn nodes(self) -> impl Iterator<Item = Result<Node>> + 'a {
+        self.map(|v| v.and_then(|v| v.into_node()))
+    }
+
+    /// Iterate the produced nodes alongside their string-values.
+    pub fn nodes_with_text(self) -> impl Iterator<Item = Result<(Node, String)>> + 'a {
+        self.nodes().map(|node| {
+            let node = node?;
+            let text = node.value()?.convert_to_string()?;
+
+            Ok((node, text))
         })
     }
+
+    /// The next `limit` nodes. The rest of the document isn't searched once they're found.
+    pub fn take_nodes(mut self, limit: usize) -> Result<Vec<Node>> {
+        if self.finished {
+            return Ok(Vec::new());
+        }
+
+        self.expr.take_nodes(&self.eval, limit)
+    }
+
+    /// Collects the produced nodes as their joined outer HTML. See [`Nodeset::to_html`].
+    pub fn to_html(self) -> Result<String> {
+        Ok(self.collect_nodes()?.to_html())
+    }
 }
 
 impl<'a> Iterator for ProduceIter<'a> {
     type Item = Result<Value>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.expr.next_eval(&self.eval).transpose()
+        if self.finished {
+            return None;
+        }
+
+        let value = self.expr.next_eval(&self.eval).transpose();
+
+        // Only node-sets are produced one node at a time. Anything else is a single value.
+        if !matches!(value, Some(Ok(Value::Node(_)))) {
+            self.finished = true;
+        }
+
+        value
     }
 }
 
 #[derive(Clone)]
 pub struct Document {
     pub root: Node,
+    pub functions: FunctionLibrary,
+    /// Values referenced as `$name` in this document's queries.
+    pub variables: HashMap<String, Value>,
+    // Only filled in by `parse_document_with_spans`.
+    pub(crate) source_spans: SourceSpans,
 }
 
 impl Document {
     pub fn new(root: Node) -> Self {
-        Self { root }
+        if let Some(handle) = root.handle() {
+            value::link_template_contents(&handle);
+        }
+
+        Self {
+            root,
+            functions: FunctionLibrary::new(),
+            variables: HashMap::new(),
+            source_spans: SourceSpans::new(),
+        }
+    }
+
+    /// Parse an HTML document from `reader`, the same as [`parse_document`](crate::parse_document).
+    ///
+    /// A failed read is an `Error::Io` holding the reader's error message.
+    pub fn from_reader<R: std::io::Read>(reader: &mut R) -> Result<Self> {
+        crate::parse_document(reader)
+    }
+
+    /// Binds `value` to `$name` in this document's queries, replacing any previous value.
+    pub fn set_variable<S: Into<String>, V: Into<Value>>(&mut self, name: S, value: V) {
+        self.variables.insert(name.into(), value.into());
+    }
+
+    /// Makes `function` callable by `name` in this document's queries.
+    pub fn register_function<S: Into<String>, F: functions::Function + 'static>(
+        &mut self,
+        name: S,
+        function: F,
+    ) {
+        self.functions.register(name, function);
+    }
+
+    /// The `markup5ever_rcdom` handle of the root node.
+    ///
+    /// Panics if the root was created from a node whose DOM has since been dropped.
+    pub fn dom_root(&self) -> markup5ever_rcdom::Handle {
+        self.root
+            .as_handle()
+            .expect("document root is no longer in a DOM")
     }
 
     pub fn evaluate<S: Into<String>>(&self, search: S) -> Result<ProduceIter<'_>> {
         self.evaluate_from(search, &self.root)
     }
 
+    pub fn evaluate_with_options<S: Into<String>>(
+        &self,
+        search: S,
+        options: EvaluationOptions,
+    ) -> Result<ProduceIter<'_>> {
+        let mut factory = Factory::new(search, self, &self.root);
+        factory.eval.options = options;
+        factory.produce()
+    }
+
+    /// Evaluate the search, failing if any of it wasn't parsed rather than ignoring the rest.
+    ///
+    /// [`Document::evaluate`] stops parsing after the first complete expression. ex: only `1` of `1 2` is evaluated.
+    /// Here anything left over is an `Error::UnexpectedToken` for the first unparsed token.
+    pub fn evaluate_strict<S: Into<String>>(&self, search: S) -> Result<ProduceIter<'_>> {
+        let mut factory = Factory::new(search, self, &self.root);
+        factory.strict = true;
+        factory.produce()
+    }
+
+    /// Evaluate the search and collect every produced value.
+    pub fn evaluate_all<S: Into<String>>(&self, search: S) -> Result<Vec<Value>> {
+        self.evaluate(search)?.collect()
+    }
+
+    /// Evaluate each query against the document, collecting the values of each.
+    pub fn evaluate_many(&self, queries: &[&str]) -> Vec<Result<Vec<Value>>> {
+        queries
+            .iter()
+            .map(|query| self.evaluate_all(*query))
+            .collect()
+    }
+
+    /// Evaluate a search expected to find at most one node.
+    /// Returns `Error::MultipleResults` if it finds more.
+    pub fn evaluate_unique<S: Into<String>>(&self, search: S) -> Result<Option<Node>> {
+        let mut nodes = self.evaluate(search)?.nodes();
+
+        let Some(node) = nodes.next().transpose()? else {
+            return Ok(None);
+        };
+
+        if nodes.next().is_some() {
+            return Err(Error::MultipleResults);
+        }
+
+        Ok(Some(node))
+    }
+
+    /// Evaluate `select` and group the nodes by the string value of `key`, evaluated from each of them.
+    ///
+    /// Groups are in the order their first node was found. A `key` which finds nothing is an empty string.
+    pub fn evaluate_grouped<S: Into<String>>(
+        &self,
+        select: S,
+        key: &str,
+    ) -> Result<Vec<(String, Vec<Node>)>> {
+        let mut groups: Vec<(String, Vec<Node>)> = Vec::new();
+        let mut group_index: HashMap<String, usize> = HashMap::new();
+
+        for node in self.evaluate(select)?.nodes() {
+            let node = node?;
+
+            let value = match self.evaluate_from(key, &node)?.next().transpose()? {
+                Some(value) => value.convert_to_string()?,
+                None => String::new(),
+            };
+
+            match group_index.get(&value) {
+                Some(&index) => groups[index].1.push(node),
+                None => {
+                    group_index.insert(value.clone(), groups.len());
+                    groups.push((value, vec![node]));
+                }
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Does the search find a node or produce a truthy value.
+    /// Searching stops at the first node found.
+    pub fn exists<S: Into<String>>(&self, search: S) -> Result<bool> {
+        let mut iter = self.evaluate(search)?;
+
+        if iter.expr.is_nodeset() {
+            return iter.expr.exists(&iter.eval);
+        }
+
+        Ok(iter
+            .next()
+            .transpose()?
+            .is_some_and(Value::convert_to_boolean))
+    }
+
     pub fn evaluate_from<'b, 'a: 'b, S: Into<String>>(
         &'a self,
         search: S,
@@ -56,9 +269,62 @@ impl Document {
         Factory::new(search, self, node).produce()
     }
 
+    /// Evaluate the search from `node` with `position()` and `last()` returning `position` and `size`.
+    ///
+    /// Useful for re-running a query on each node of a loop driven outside of XPath.
+    pub fn evaluate_with_context<'b, 'a: 'b, S: Into<String>>(
+        &'a self,
+        search: S,
+        node: &'a Node,
+        position: usize,
+        size: usize,
+    ) -> Result<ProduceIter<'b>> {
+        let mut factory = Factory::new(search, self, node);
+        factory.eval = Evaluation::new_with_context(node, self, position, size);
+        factory.produce()
+    }
+
     pub fn evaluate_steps(&self, steps: Vec<ExprToken>) -> Result<ProduceIter> {
         Factory::new_from_steps(steps, self, &self.root).produce()
     }
+
+    /// The text of every text node in document order. The same as evaluating `string(/)`.
+    pub fn all_text(&self) -> String {
+        // The root's string-value can't fail.
+        self.root.get_string_value().unwrap_or_default()
+    }
+
+    /// Total amount of nodes in the document, including the root. Attributes aren't counted.
+    pub fn node_count(&self) -> usize {
+        self.tree_stats().0
+    }
+
+    /// How many levels below the root the most deeply nested node is.
+    pub fn max_depth(&self) -> usize {
+        self.tree_stats().1
+    }
+
+    // Walk the whole tree once, returning the node count and the max depth.
+    fn tree_stats(&self) -> (usize, usize) {
+        let mut count = 0;
+        let mut max_depth = 0;
+
+        let mut stack = vec![(self.root.clone(), 0)];
+
+        while let Some((node, depth)) = stack.pop() {
+            count += 1;
+            max_depth = max_depth.max(depth);
+
+            stack.extend(node.children_iter().map(|child| (child, depth + 1)));
+        }
+
+        (count, max_depth)
+    }
+
+    /// Find the namespace uri bound to `prefix` anywhere in the document.
+    pub fn lookup_namespace(&self, prefix: &str) -> Option<String> {
+        value::find_namespace_uri(&self.root.handle()?, prefix)
+    }
 }
 
 macro_rules! return_value {
@@ -76,7 +342,11 @@ pub struct Factory<'eval> {
     eval: Evaluation<'eval>,
     tokenizer: Tokenizer,
     token_steps: Vec<ExprToken>,
+    // Query offset of the token each step was expanded from.
+    token_offsets: Vec<usize>,
     error: Option<Error>,
+    // Fail on tokens left over after parsing instead of ignoring them.
+    strict: bool,
 }
 
 impl<'eval, 'b: 'eval> Factory<'eval> {
@@ -85,7 +355,9 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
             eval: Evaluation::new(node, document),
             tokenizer: Tokenizer::new(query),
             token_steps: Vec::new(),
+            token_offsets: Vec::new(),
             error: None,
+            strict: false,
         }
     }
 
@@ -98,16 +370,24 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
             eval: Evaluation::new(node, document),
             tokenizer: Tokenizer::new(""),
             token_steps: steps,
+            token_offsets: Vec::new(),
             error: None,
+            strict: false,
         }
     }
 
     // Parse query, place tokens into token_steps.
     fn tokenize(&mut self) {
         while !self.tokenizer.is_finished() {
+            let offset = self.tokenizer.position();
+
             match self.tokenizer.next().unwrap() {
-                Ok(step) => self.expand_abbreviation(step),
+                Ok(step) => {
+                    self.expand_abbreviation(step);
+                    self.token_offsets.resize(self.token_steps.len(), offset);
+                }
                 Err(error) => {
+                    let error = self.parse_error(error, offset);
                     error!(?error);
                     self.error = Some(error);
                     return;
@@ -163,41 +443,114 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
     pub fn produce(mut self) -> Result<ProduceIter<'eval>> {
         self.tokenize();
 
-        if self.error.is_none() {
-            if tracing::enabled!(Level::TRACE) {
-                trace!("Steps");
-                self.token_steps.iter().for_each(|t| trace!(" - {:?}", t));
-            }
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        self.check_parentheses()?;
+
+        if tracing::enabled!(Level::TRACE) {
+            trace!("Steps");
+            self.token_steps.iter().for_each(|t| trace!(" - {:?}", t));
+        }
+
+        let mut stepper = Stepper::new(self.token_steps.clone().into_iter().peekable());
 
-            let mut stepper = Stepper::new(self.token_steps.clone().into_iter().peekable());
+        if stepper.has_more_tokens() {
+            let expr = self
+                .parse_expression(&mut stepper)
+                .map_err(|e| self.parse_error(e, self.step_position(stepper.consumed())))?;
 
-            if stepper.has_more_tokens() {
-                let expr = self.parse_expression(&mut stepper)?;
+            // A dangling '::' which wasn't attached to an axis name. ex: 'div::'
+            if stepper.is_next_token(ExprToken::LocationStep) {
+                return Err(self.parse_error(
+                    Error::UnexpectedToken(ExprToken::LocationStep),
+                    self.step_position(stepper.consumed()),
+                ));
+            }
 
-                match expr {
-                    Some(expr) => {
-                        trace!("Parsed: {:#?}", expr);
-                        return Ok(ProduceIter::<'eval> {
-                            expr,
-                            eval: self.eval,
-                        });
-                    }
+            // Part of the query left after the expression. ex: the ' 2' of '1 2'
+            if self.strict {
+                let position = self.step_position(stepper.consumed());
 
-                    None => {
-                        // Couldn't find it. Invalid xpath.
-                        return Err(Error::InvalidXpath);
-                    }
+                if let Some(token) = stepper.next() {
+                    return Err(self.parse_error(Error::UnexpectedToken(token), position));
                 }
             }
 
-            if !stepper.has_more_tokens() {
-                trace!("Finished.");
+            match expr {
+                Some(expr) => {
+                    trace!("Parsed: {:#?}", expr);
+                    return Ok(ProduceIter::<'eval> {
+                        expr,
+                        eval: self.eval,
+                        finished: false,
+                    });
+                }
+
+                None => {
+                    // Couldn't find it. Invalid xpath.
+                    return Err(self
+                        .parse_error(Error::InvalidXpath, self.step_position(stepper.consumed())));
+                }
             }
         }
 
+        if !stepper.has_more_tokens() {
+            trace!("Finished.");
+        }
+
         Err(Error::UnableToEvaluate)
     }
 
+    // Query offset of the first token step which hasn't been parsed.
+    fn step_position(&self, consumed_steps: usize) -> usize {
+        self.token_offsets
+            .get(consumed_steps)
+            .copied()
+            .unwrap_or_else(|| self.tokenizer.query().len())
+    }
+
+    // Every '(' needs a ')' after it. The error points at the unmatched one.
+    fn check_parentheses(&self) -> Result<()> {
+        let mut open = Vec::new();
+
+        for (index, token) in self.token_steps.iter().enumerate() {
+            match token {
+                ExprToken::LeftParen => open.push(index),
+                ExprToken::RightParen if open.pop().is_none() => {
+                    return Err(
+                        self.parse_error(Error::UnbalancedParentheses, self.step_position(index))
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        match open.pop() {
+            Some(index) => {
+                Err(self.parse_error(Error::UnbalancedParentheses, self.step_position(index)))
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Attach where in the query parsing failed.
+    fn parse_error(&self, error: Error, position: usize) -> Error {
+        let query = self.tokenizer.query();
+
+        // Built from token steps, there's no query to point into.
+        if query.is_empty() {
+            return error;
+        }
+
+        Error::Parse {
+            error: Box::new(error),
+            consumed: query[..position].to_string(),
+            remaining: query[position..].to_string(),
+        }
+    }
+
     // Parse Types
 
     // Expr					::= OrExpr
@@ -213,20 +566,20 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
         &self,
         step: &mut Stepper<S>,
     ) -> ExpressionResult {
-        let left_expr = self.parse_and_expression(step)?;
+        let Some(mut left_expr) = self.parse_and_expression(step)? else {
+            return Ok(None);
+        };
 
         // Self 'or' AndExpr
-        if step.consume_if_next_token_is(Operator::Or)? {
-            let right_expr = self.parse_relational_expression(step)?;
+        while step.consume_if_next_token_is(Operator::Or)? {
+            let right_expr = self
+                .parse_and_expression(step)?
+                .ok_or_else(|| Error::ExpectedRightHandExpression(Operator::Or.into()))?;
 
-            return Ok(Some(Box::new(Or::new(
-                left_expr.unwrap(),
-                right_expr
-                    .ok_or_else(|| Error::ExpectedRightHandExpression(Operator::Or.into()))?,
-            ))));
+            left_expr = Box::new(Or::new(left_expr, right_expr));
         }
 
-        Ok(left_expr)
+        Ok(Some(left_expr))
     }
 
     // AndExpr				::= EqualityExpr | Self 'and' EqualityExpr
@@ -234,20 +587,20 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
         &self,
         step: &mut Stepper<S>,
     ) -> ExpressionResult {
-        let left_expr = self.parse_equality_expression(step)?;
+        let Some(mut left_expr) = self.parse_equality_expression(step)? else {
+            return Ok(None);
+        };
 
         // Self 'and' EqualityExpr
-        if step.consume_if_next_token_is(Operator::And)? {
-            let right_expr = self.parse_relational_expression(step)?;
+        while step.consume_if_next_token_is(Operator::And)? {
+            let right_expr = self
+                .parse_equality_expression(step)?
+                .ok_or_else(|| Error::ExpectedRightHandExpression(Operator::And.into()))?;
 
-            return Ok(Some(Box::new(And::new(
-                left_expr.unwrap(),
-                right_expr
-                    .ok_or_else(|| Error::ExpectedRightHandExpression(Operator::And.into()))?,
-            ))));
+            left_expr = Box::new(And::new(left_expr, right_expr));
         }
 
-        Ok(left_expr)
+        Ok(Some(left_expr))
     }
 
     // EqualityExpr			::= RelationalExpr | Self '=' RelationalExpr | Self '!=' RelationalExpr
@@ -255,32 +608,33 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
         &self,
         step: &mut Stepper<S>,
     ) -> ExpressionResult {
-        let left_expr = self.parse_relational_expression(step)?;
-
-        // Self '=' RelationalExpr
-        if step.consume_if_next_token_is(Operator::Equal)? {
-            let right_expr = self.parse_relational_expression(step)?;
+        let Some(mut left_expr) = self.parse_relational_expression(step)? else {
+            return Ok(None);
+        };
 
-            return Ok(Some(Box::new(Equal::new(
-                left_expr.unwrap(),
-                right_expr
-                    .ok_or_else(|| Error::ExpectedRightHandExpression(Operator::Equal.into()))?,
-            ))));
-        }
+        // Left associative. ex: '1 = 2 = false()' is '(1 = 2) = false()'
+        loop {
+            let operator = if step.consume_if_next_token_is(Operator::Equal)? {
+                Operator::Equal
+            } else if step.consume_if_next_token_is(Operator::DoesNotEqual)? {
+                Operator::DoesNotEqual
+            } else {
+                break;
+            };
 
-        // Self '!=' RelationalExpr
-        if step.consume_if_next_token_is(Operator::DoesNotEqual)? {
-            let right_expr = self.parse_relational_expression(step)?;
+            let right_expr = self
+                .parse_relational_expression(step)?
+                .ok_or_else(|| Error::ExpectedRightHandExpression(operator.into()))?;
 
-            return Ok(Some(Box::new(NotEqual::new(
-                left_expr.unwrap(),
-                right_expr.ok_or_else(|| {
-                    Error::ExpectedRightHandExpression(Operator::DoesNotEqual.into())
-                })?,
-            ))));
+            left_expr = match operator {
+                // Self '=' RelationalExpr
+                Operator::Equal => Box::new(Equal::new(left_expr, right_expr)),
+                // Self '!=' RelationalExpr
+                _ => Box::new(NotEqual::new(left_expr, right_expr)),
+            };
         }
 
-        Ok(left_expr)
+        Ok(Some(left_expr))
     }
 
     // RelationalExpr		::= AdditiveExpr | Self '<' AdditiveExpr | Self '>' AdditiveExpr | Self '<=' AdditiveExpr | Self '>=' AdditiveExpr
@@ -288,61 +642,41 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
         &self,
         step: &mut Stepper<S>,
     ) -> ExpressionResult {
-        let left_expr = self.parse_additive_expression(step)?;
-
-        // Self '<' AdditiveExpr
-        if step.consume_if_next_token_is(Operator::LessThan)? {
-            let right_expr = self.parse_relational_expression(step)?;
-
-            return Ok(Some(Box::new(LessThan::new(
-                left_expr.unwrap(),
-                right_expr
-                    .ok_or_else(|| Error::ExpectedRightHandExpression(Operator::LessThan.into()))?,
-            ))));
-        }
-
-        // Self '<=' AdditiveExpr
-        if step.consume_if_next_token_is(Operator::LessThanOrEqual)? {
-            let right_expr = self.parse_relational_expression(step)?;
-
-            return Ok(Some(Box::new(LessThanEqual::new(
-                left_expr.unwrap(),
-                right_expr.ok_or_else(|| {
-                    Error::ExpectedRightHandExpression(Operator::LessThanOrEqual.into())
-                })?,
-            ))));
-        }
-
-        // Self '>' AdditiveExpr
-        if step.consume_if_next_token_is(Operator::GreaterThan)? {
-            let right_expr = self.parse_relational_expression(step)?;
-
-            return Ok(Some(Box::new(GreaterThan::new(
-                left_expr.unwrap(),
-                right_expr.ok_or_else(|| {
-                    Error::ExpectedRightHandExpression(Operator::GreaterThan.into())
-                })?,
-            ))));
-        }
-
-        // TODO
-        // Value      3 > 2  > 1   ||   3 > 2 = false (0) > 1 = false
-        // currently  3 > (2 > 1)
-        // should be (3 > 2) > 1
+        let Some(mut left_expr) = self.parse_additive_expression(step)? else {
+            return Ok(None);
+        };
 
-        // Self '>=' AdditiveExpr
-        if step.consume_if_next_token_is(Operator::GreaterThanOrEqual)? {
-            let right_expr = self.parse_relational_expression(step)?;
+        // Left associative. ex: '3 > 2 > 1' is '(3 > 2) > 1', which is false
+        loop {
+            let operator = if step.consume_if_next_token_is(Operator::LessThan)? {
+                Operator::LessThan
+            } else if step.consume_if_next_token_is(Operator::LessThanOrEqual)? {
+                Operator::LessThanOrEqual
+            } else if step.consume_if_next_token_is(Operator::GreaterThan)? {
+                Operator::GreaterThan
+            } else if step.consume_if_next_token_is(Operator::GreaterThanOrEqual)? {
+                Operator::GreaterThanOrEqual
+            } else {
+                break;
+            };
 
-            return Ok(Some(Box::new(GreaterThanEqual::new(
-                left_expr.unwrap(),
-                right_expr.ok_or_else(|| {
-                    Error::ExpectedRightHandExpression(Operator::GreaterThanOrEqual.into())
-                })?,
-            ))));
+            let right_expr = self
+                .parse_additive_expression(step)?
+                .ok_or_else(|| Error::ExpectedRightHandExpression(operator.into()))?;
+
+            left_expr = match operator {
+                // Self '<' AdditiveExpr
+                Operator::LessThan => Box::new(LessThan::new(left_expr, right_expr)),
+                // Self '<=' AdditiveExpr
+                Operator::LessThanOrEqual => Box::new(LessThanEqual::new(left_expr, right_expr)),
+                // Self '>' AdditiveExpr
+                Operator::GreaterThan => Box::new(GreaterThan::new(left_expr, right_expr)),
+                // Self '>=' AdditiveExpr
+                _ => Box::new(GreaterThanEqual::new(left_expr, right_expr)),
+            };
         }
 
-        Ok(left_expr)
+        Ok(Some(left_expr))
     }
 
     // AdditiveExpr			::= MultiplicativeExpr | Self '+' MultiplicativeExpr | Self '-' MultiplicativeExpr
@@ -350,31 +684,33 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
         &self,
         step: &mut Stepper<S>,
     ) -> ExpressionResult {
-        let left_expr = self.parse_multiplicative_expression(step)?;
-
-        // Self '+' MultiplicativeExpr
-        if step.consume_if_next_token_is(Operator::Plus)? {
-            let right_expr = self.parse_multiplicative_expression(step)?;
+        let Some(mut left_expr) = self.parse_multiplicative_expression(step)? else {
+            return Ok(None);
+        };
 
-            return Ok(Some(Box::new(Addition::new(
-                left_expr.unwrap(),
-                right_expr
-                    .ok_or_else(|| Error::ExpectedRightHandExpression(Operator::Plus.into()))?,
-            ))));
-        }
+        // Left associative. ex: '1 - 2 + 3' is '(1 - 2) + 3'
+        loop {
+            let operator = if step.consume_if_next_token_is(Operator::Plus)? {
+                Operator::Plus
+            } else if step.consume_if_next_token_is(Operator::Minus)? {
+                Operator::Minus
+            } else {
+                break;
+            };
 
-        // Self '-' MultiplicativeExpr
-        if step.consume_if_next_token_is(Operator::Minus)? {
-            let right_expr = self.parse_multiplicative_expression(step)?;
+            let right_expr = self
+                .parse_multiplicative_expression(step)?
+                .ok_or_else(|| Error::ExpectedRightHandExpression(operator.into()))?;
 
-            return Ok(Some(Box::new(Subtraction::new(
-                left_expr.unwrap(),
-                right_expr
-                    .ok_or_else(|| Error::ExpectedRightHandExpression(Operator::Minus.into()))?,
-            ))));
+            left_expr = match operator {
+                // Self '+' MultiplicativeExpr
+                Operator::Plus => Box::new(Addition::new(left_expr, right_expr)),
+                // Self '-' MultiplicativeExpr
+                _ => Box::new(Subtraction::new(left_expr, right_expr)),
+            };
         }
 
-        Ok(left_expr)
+        Ok(Some(left_expr))
     }
 
     // MultiplicativeExpr	::= UnaryExpr | Self MultiplyOperator UnaryExpr | Self 'div' UnaryExpr | Self 'mod' UnaryExpr
@@ -382,13 +718,37 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
         &self,
         step: &mut Stepper<S>,
     ) -> ExpressionResult {
-        let left_expr = self.parse_unary_expression(step)?;
+        let Some(mut left_expr) = self.parse_unary_expression(step)? else {
+            return Ok(None);
+        };
+
+        // Left associative. ex: '8 div 4 div 2' is '(8 div 4) div 2'
+        loop {
+            let operator = if step.consume_if_next_token_is(Operator::Star)? {
+                Operator::Star
+            } else if step.consume_if_next_token_is(Operator::Div)? {
+                Operator::Div
+            } else if step.consume_if_next_token_is(Operator::Mod)? {
+                Operator::Mod
+            } else {
+                break;
+            };
 
-        // Self MultiplyOperator UnaryExpr
-        // Self 'div' UnaryExpr
-        // Self 'mod' UnaryExpr
+            let right_expr = self
+                .parse_unary_expression(step)?
+                .ok_or_else(|| Error::ExpectedRightHandExpression(operator.into()))?;
+
+            left_expr = match operator {
+                // Self MultiplyOperator UnaryExpr
+                Operator::Star => Box::new(Multiplication::new(left_expr, right_expr)),
+                // Self 'div' UnaryExpr
+                Operator::Div => Box::new(Division::new(left_expr, right_expr)),
+                // Self 'mod' UnaryExpr
+                _ => Box::new(Modulo::new(left_expr, right_expr)),
+            };
+        }
 
-        Ok(left_expr)
+        Ok(Some(left_expr))
     }
 
     // UnaryExpr			::= UnionExpr | '-' Self
@@ -414,20 +774,20 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
         &self,
         step: &mut Stepper<S>,
     ) -> ExpressionResult {
-        let left_expr = self.parse_path_expression(step)?;
+        let Some(mut left_expr) = self.parse_path_expression(step)? else {
+            return Ok(None);
+        };
 
         // Self '|' PathExpr
-        if step.consume_if_next_token_is(Operator::Pipe)? {
-            let right_expr = self.parse_path_expression(step)?;
+        while step.consume_if_next_token_is(Operator::Pipe)? {
+            let right_expr = self
+                .parse_path_expression(step)?
+                .ok_or_else(|| Error::ExpectedRightHandExpression(Operator::Pipe.into()))?;
 
-            return Ok(Some(Box::new(Union::new(
-                left_expr.unwrap(),
-                right_expr
-                    .ok_or_else(|| Error::ExpectedRightHandExpression(Operator::Pipe.into()))?,
-            ))));
+            left_expr = Box::new(Union::new(left_expr, right_expr));
         }
 
-        Ok(left_expr)
+        Ok(Some(left_expr))
     }
 
     // Path
@@ -453,7 +813,7 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
 
                     let expr = self.parse_location_path_raw(step, expr)?;
 
-                    Ok(Some(expr.expect("parse_path_expression")))
+                    Ok(Some(expr.ok_or(Error::TrailingSlash)?))
                 } else {
                     Ok(Some(expr))
                 }
@@ -519,21 +879,8 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
                 while step.is_next_token(Operator::ForwardSlash) {
                     step.consume(Operator::ForwardSlash)?;
 
-                    if step.is_next_token(Operator::Star) {
-                        step.consume(Operator::Star)?;
-
-                        steps.push(Step::new(
-                            AxisName::Child,
-                            Box::new(nodetest::Element::new(nodetest::NameTest {
-                                prefix: None,
-                                local_part: "*".into(),
-                            })),
-                            Vec::new(),
-                        ));
-                    } else {
-                        let next = self.parse_step(step)?;
-                        steps.push(next.ok_or(Error::TrailingSlash)?);
-                    }
+                    let next = self.parse_step(step)?;
+                    steps.push(next.ok_or(Error::TrailingSlash)?);
                 }
 
                 Ok(Some(Box::new(Path::new(start_point, steps))))
@@ -573,8 +920,20 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
         &self,
         step: &mut Stepper<S>,
     ) -> Result<AxisName> {
+        // '::' without an axis name in front of it. ex: '::div'
+        if step.is_next_token(ExprToken::LocationStep) {
+            return Err(Error::UnexpectedToken(ExprToken::LocationStep));
+        }
+
         if step.is_next_token_func(|t| t.is_axis()) {
-            Ok(return_value!(step, ExprToken::Axis))
+            let axis = return_value!(step, ExprToken::Axis);
+
+            // Not implemented yet, fail the query rather than panicking while evaluating it.
+            if axis == AxisName::Namespace {
+                return Err(Error::UnsupportedAxis(axis));
+            }
+
+            Ok(axis)
         } else {
             Ok(AxisName::Child)
         }
@@ -588,13 +947,13 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
         step: &mut Stepper<S>,
     ) -> ExpressionResult {
         if let Some(expr) = self.parse_primary_expression(step)? {
-            // let predicates = self.parse_predicates(step)?;
-
-            // Ok(Some(predicates.into_iter().fold(expr, |expr, pred| {
-            //     Filter::new(expr, pred)
-            // })))
+            let predicates = self.parse_predicate_expressions(step)?;
 
-            Ok(Some(expr))
+            if predicates.is_empty() {
+                Ok(Some(expr))
+            } else {
+                Ok(Some(Box::new(Filter::new(expr, predicates))))
+            }
         } else {
             Ok(None)
         }
@@ -609,8 +968,34 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
         &self,
         step: &mut Stepper<S>,
     ) -> ExpressionResult {
-        // self.parse_variable_reference(step)
-        // self.parse_nested_expression(step)
+        if let Some(ExprToken::VariableReference(name)) = step.peek() {
+            // Looked up before the token is taken so an error points at the reference, not after it.
+            let Some(value) = self.eval.variable(name) else {
+                return Err(Error::UnknownVariable(name.clone()));
+            };
+
+            let variable = Variable::new(value.clone());
+
+            step.next();
+
+            return Ok(Some(Box::new(variable)));
+        }
+
+        if step.is_next_token(ExprToken::LeftParen) {
+            step.consume(ExprToken::LeftParen)?;
+
+            // Nothing inside the parentheses. ex: '()'
+            let Some(expr) = self.parse_expression(step)? else {
+                return Err(step
+                    .next()
+                    .map_or(Error::InputEmpty, Error::UnexpectedToken));
+            };
+
+            step.consume(ExprToken::RightParen)?;
+
+            return Ok(Some(expr));
+        }
+
         // self.parse_string_literal(step)
         if step.is_next_token_func(|i| i.is_literal()) {
             let value = return_value!(step, ExprToken::Literal);
@@ -651,12 +1036,15 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
                 "string" => Box::new(functions::ToString),
                 "concat" => Box::new(functions::Concat),
                 "starts-with" => Box::new(functions::StartsWith),
+                "ends-with" => Box::new(functions::EndsWith),
                 "contains" => Box::new(functions::Contains),
+                "contains-token" => Box::new(functions::ContainsToken),
                 "substring-before" => Box::new(functions::SubstringBefore),
                 "substring-after" => Box::new(functions::SubstringAfter),
                 "substring" => Box::new(functions::Substring),
                 "string-length" => Box::new(functions::StringLength),
                 "normalize-space" => Box::new(functions::NormalizeSpace),
+                "boolean" => Box::new(functions::ToBoolean),
                 "not" => Box::new(functions::Not),
                 "true" => Box::new(functions::True),
                 "false" => Box::new(functions::False),
@@ -665,15 +1053,23 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
                 "ceiling" => Box::new(functions::Ceiling),
                 "round" => Box::new(functions::Round),
 
-                _ => return Ok(None),
+                name => match self.eval.functions().get(name) {
+                    Some(function) => Box::new(function),
+                    None => return Ok(None),
+                },
             };
 
             let mut args = Vec::new();
 
             while !step.consume_if_next_token_is(ExprToken::RightParen)? {
-                if let Some(expr) = self.parse_expression(step)? {
-                    args.push(expr);
-                }
+                // Neither an argument nor the closing parenthesis. ex: 'count(])'
+                let Some(expr) = self.parse_expression(step)? else {
+                    return Err(step
+                        .next()
+                        .map_or(Error::InputEmpty, Error::UnexpectedToken));
+                };
+
+                args.push(expr);
 
                 step.consume_if_next_token_is(ExprToken::Comma)?;
             }
@@ -701,13 +1097,6 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
                     Ok(Some(Box::new(nodetest::ProcessingInstruction::new(target))))
                 }
             }
-        } else if step.is_next_token(Operator::Star) {
-            step.consume(Operator::Star)?;
-
-            Ok(Some(Box::new(nodetest::Element::new(nodetest::NameTest {
-                prefix: None,
-                local_part: "*".into(),
-            }))))
         } else {
             Ok(None)
         }
@@ -759,7 +1148,9 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
 
             let val = self.parse_expression(step)?;
 
-            step.consume(ExprToken::RightBracket)?;
+            if !step.consume_if_next_token_is(ExprToken::RightBracket)? {
+                return Err(Error::ExpectedClosingBracket);
+            }
 
             Ok(val)
         } else {
@@ -781,11 +1172,19 @@ impl<'eval, 'b: 'eval> Factory<'eval> {
 
 //
 
-pub struct Stepper<S: Iterator<Item = ExprToken>>(Peekable<S>);
+pub struct Stepper<S: Iterator<Item = ExprToken>> {
+    steps: Peekable<S>,
+    consumed: usize,
+}
 
 impl<S: Iterator<Item = ExprToken>> Stepper<S> {
     pub fn new(steps: Peekable<S>) -> Self {
-        Stepper(steps)
+        Stepper { steps, consumed: 0 }
+    }
+
+    /// The amount of tokens taken so far.
+    pub fn consumed(&self) -> usize {
+        self.consumed
     }
 
     pub fn has_more_tokens(&mut self) -> bool {
@@ -840,7 +1239,7 @@ impl<S: Iterator<Item = ExprToken>> Stepper<S> {
     }
 
     pub fn peek(&mut self) -> Option<&S::Item> {
-        self.0.peek()
+        self.steps.peek()
     }
 }
 
@@ -848,6 +1247,8 @@ impl<S: Iterator<Item = ExprToken>> Iterator for Stepper<S> {
     type Item = S::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next()
+        let step = self.steps.next()?;
+        self.consumed += 1;
+        Some(step)
     }
 }
@@ -1,13 +1,58 @@
+use std::collections::HashMap;
 use std::fmt;
+use std::rc::Rc;
 
 use crate::result::{Error, ValueError};
-use crate::{Result, Value};
+use crate::{Node, Nodeset, Result, Value};
 
-use crate::expressions::Expression;
+use crate::expressions::{produced_values, Expression};
 use crate::Evaluation;
 
+/// An XPath function. Built-in functions and ones registered in a [`FunctionLibrary`] implement it.
 pub trait Function: fmt::Debug {
+    /// Called each time the function is evaluated.
+    ///
+    /// `eval` is the context it's called in: `eval.node` is the context node and
+    /// `eval.position`/`eval.size` are what `position()`/`last()` would return.
+    /// The arguments are unevaluated expressions, evaluate them against the same `eval`.
     fn exec<'a>(&self, eval: &Evaluation, args: Args<'a>) -> Result<Value>;
+
+    /// Does the result only depend on `eval.size` and the arguments, never the context node or position.
+    ///
+    /// If so, a predicate calling it is evaluated once for all of its nodes. ex: 'last()'
+    fn only_uses_context_size(&self) -> bool {
+        false
+    }
+}
+
+impl Function for Rc<dyn Function> {
+    fn exec<'a>(&self, eval: &Evaluation, args: Args<'a>) -> Result<Value> {
+        (**self).exec(eval, args)
+    }
+
+    fn only_uses_context_size(&self) -> bool {
+        (**self).only_uses_context_size()
+    }
+}
+
+/// User functions, called by name in queries against the [`Document`](crate::Document) holding them.
+///
+/// Built-in functions take precedence over registered ones with the same name.
+#[derive(Debug, Default, Clone)]
+pub struct FunctionLibrary(HashMap<String, Rc<dyn Function>>);
+
+impl FunctionLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<S: Into<String>, F: Function + 'static>(&mut self, name: S, function: F) {
+        self.0.insert(name.into(), Rc::new(function));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<dyn Function>> {
+        self.0.get(name).cloned()
+    }
 }
 
 pub struct Args<'a>(&'a mut [Box<dyn Expression>]);
@@ -21,10 +66,9 @@ impl<'a> Args<'a> {
         self.get_optional(index).ok_or(Error::MissingFuncArgument)
     }
 
+    /// An argument which produced nothing is an empty node-set. ex: '//missing'
     pub fn get_required_value(&mut self, index: usize, eval: &Evaluation) -> Result<Value> {
-        self.get_required(index)?
-            .next_eval(eval)?
-            .ok_or(Error::UnableToFindValue)
+        Ok(value_or_empty(self.get_required(index)?.next_eval(eval)?))
     }
 
     pub fn get_required_optional_value(
@@ -44,6 +88,11 @@ impl<'a> Args<'a> {
     }
 }
 
+// An expression which produced nothing evaluated to an empty node-set.
+fn value_or_empty(value: Option<Value>) -> Value {
+    value.unwrap_or_else(|| Value::Nodeset(Nodeset::new()))
+}
+
 // Node Set Functions
 
 // number last()
@@ -54,6 +103,10 @@ impl Function for Last {
     fn exec<'a>(&self, eval: &Evaluation, _: Args<'a>) -> Result<Value> {
         Ok(Value::Number(eval.size as f64))
     }
+
+    fn only_uses_context_size(&self) -> bool {
+        true
+    }
 }
 
 // number position()
@@ -76,8 +129,11 @@ impl Function for Count {
 
         let mut count = 0.0;
 
-        while arg1.next_eval(eval)?.is_some() {
-            count += 1.0;
+        while let Some(value) = arg1.next_eval(eval)? {
+            count += match value {
+                Value::Nodeset(set) => set.len() as f64,
+                _ => 1.0,
+            };
         }
 
         Ok(Value::Number(count))
@@ -91,20 +147,14 @@ impl Function for Count {
 pub struct LocalName;
 
 impl Function for LocalName {
-    fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
-        if let Some(expr) = args.get_optional(0) {
-            if let Some(node) = expr.next_eval(eval)? {
-                let node = node.into_node()?;
-
-                let qual = node
-                    .name()
-                    .ok_or_else::<Error, _>(|| ValueError::Nodeset.into())?;
-
-                return Ok(Value::String(qual.local.to_string()));
-            }
-        }
+    fn exec<'a>(&self, eval: &Evaluation, args: Args<'a>) -> Result<Value> {
+        // The root, text and comment nodes have no name.
+        let value = match named_node_arg(eval, args)?.and_then(|node| node.name()) {
+            Some(qual) => qual.local.to_string(),
+            None => String::new(),
+        };
 
-        Ok(Value::String(String::new()))
+        Ok(Value::String(value))
     }
 }
 
@@ -113,19 +163,13 @@ impl Function for LocalName {
 pub struct NamespaceUri;
 
 impl Function for NamespaceUri {
-    fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
-        if let Some(expr) = args.get_optional(0) {
-            if let Some(node) = expr.next_eval(eval)? {
-                let node = node.into_node()?;
-
-                let qual = node
-                    .name()
-                    .ok_or_else::<Error, _>(|| ValueError::Nodeset.into())?;
-                return Ok(Value::String(qual.ns.to_string()));
-            }
-        }
+    fn exec<'a>(&self, eval: &Evaluation, args: Args<'a>) -> Result<Value> {
+        let value = match named_node_arg(eval, args)?.and_then(|node| node.name()) {
+            Some(qual) => qual.ns.to_string(),
+            None => String::new(),
+        };
 
-        Ok(Value::String(String::new()))
+        Ok(Value::String(value))
     }
 }
 
@@ -134,29 +178,28 @@ impl Function for NamespaceUri {
 pub struct Name;
 
 impl Function for Name {
-    fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
-        if let Some(expr) = args.get_optional(0) {
-            if let Some(node) = expr.next_eval(eval)? {
-                let node = node.into_node()?;
-
-                let qual = node
-                    .name()
-                    .ok_or_else::<Error, _>(|| ValueError::Nodeset.into())?;
-
-                let value = if let Some(mut prefix) = qual.prefix.map(|s| s.to_string()) {
-                    prefix += ":";
-                    prefix += &qual.local;
+    fn exec<'a>(&self, eval: &Evaluation, args: Args<'a>) -> Result<Value> {
+        let value = match named_node_arg(eval, args)?.and_then(|node| node.name()) {
+            Some(qual) => match qual.prefix {
+                Some(prefix) => format!("{prefix}:{}", qual.local),
+                None => qual.local.to_string(),
+            },
+            None => String::new(),
+        };
 
-                    prefix
-                } else {
-                    qual.local.to_string()
-                };
+        Ok(Value::String(value))
+    }
+}
 
-                return Ok(Value::String(value));
-            }
-        }
+// The first node of the argument, or the context node without one. An empty node-set is None.
+fn named_node_arg(eval: &Evaluation, mut args: Args<'_>) -> Result<Option<Node>> {
+    match args.get_optional(0) {
+        Some(expr) => match expr.next_eval(eval)? {
+            Some(value) => Ok(Some(value.into_node()?)),
+            None => Ok(None),
+        },
 
-        Ok(Value::String(String::new()))
+        None => Ok(Some(eval.node.clone())),
     }
 }
 
@@ -168,12 +211,7 @@ pub struct ToString;
 
 impl Function for ToString {
     fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
-        let value = match args.get_required_value(0, eval)? {
-            Value::Boolean(val) => val.to_string(),
-            Value::Number(val) => val.to_string(),
-            Value::String(val) => val,
-            Value::Node(n) => format!("{n:?}"), // TODO
-        };
+        let value = args.get_required_value(0, eval)?.convert_to_string()?;
 
         Ok(Value::String(value))
     }
@@ -188,37 +226,54 @@ impl Function for Concat {
         let mut concat_value = String::new();
 
         for expr in args.as_array() {
-            // It's okay if we don't find the value. We will not insert anything.
-            if let Some(value_eval) = expr.next_eval(eval)? {
-                let string_value = value_eval.convert_to_string()?;
-
-                concat_value.push_str(&string_value);
-            }
+            concat_value.push_str(&first_string_value(expr, eval)?);
         }
 
         Ok(Value::String(concat_value))
     }
 }
 
+// The argument converted to a string. A node-set is the string-value of its first node in document order.
+fn first_string_value(expr: &mut Box<dyn Expression>, eval: &Evaluation) -> Result<String> {
+    let mut values = produced_values(expr, eval)?;
+
+    let value = match values.len() {
+        0 => return Ok(String::new()),
+        1 => values.remove(0),
+        _ => Value::Nodeset(
+            values
+                .into_iter()
+                .map(Value::into_node)
+                .collect::<Result<Vec<_>>>()?
+                .into(),
+        ),
+    };
+
+    value.convert_to_string()
+}
+
 // boolean starts-with(string, string)
 #[derive(Debug)]
 pub struct StartsWith;
 
 impl Function for StartsWith {
-    fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
-        // Required since if the required value does not contain wanted result it will error with UnableToFindValue. We don't want an error. We want a Boolean(false).
-        let (left, right) = match (
-            args.get_required_optional_value(0, eval)?,
-            args.get_required_optional_value(1, eval)?,
-        ) {
-            (Some(a), Some(b)) => (a, b),
-            (None, None) | (None, Some(_)) | (Some(_), None) => return Ok(Value::Boolean(false)),
-        };
+    fn exec<'a>(&self, eval: &Evaluation, args: Args<'a>) -> Result<Value> {
+        let (left, right) = two_string_args(eval, args)?;
 
-        let left_value = left.convert_to_string()?;
-        let right_value = right.convert_to_string()?;
+        Ok(Value::Boolean(left.starts_with(&right)))
+    }
+}
 
-        Ok(Value::Boolean(left_value.starts_with(&right_value)))
+// https://www.w3.org/TR/xpath-functions-31/#func-ends-with
+// boolean ends-with(string, string)
+#[derive(Debug)]
+pub struct EndsWith;
+
+impl Function for EndsWith {
+    fn exec<'a>(&self, eval: &Evaluation, args: Args<'a>) -> Result<Value> {
+        let (left, right) = two_string_args(eval, args)?;
+
+        Ok(Value::Boolean(left.ends_with(&right)))
     }
 }
 
@@ -227,28 +282,44 @@ impl Function for StartsWith {
 pub struct Contains;
 
 impl Function for Contains {
-    fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
-        // Required since if the required value does not contain wanted result it will error with UnableToFindValue. We don't want an error. We want a Boolean(false).
-        let (left, right) = match (
-            args.get_required_optional_value(0, eval)?,
-            args.get_required_optional_value(1, eval)?,
-        ) {
-            (Some(a), Some(b)) => (a, b),
-            (None, None) | (None, Some(_)) | (Some(_), None) => return Ok(Value::Boolean(false)),
-        };
+    fn exec<'a>(&self, eval: &Evaluation, args: Args<'a>) -> Result<Value> {
+        let (left, right) = two_string_args(eval, args)?;
 
-        let left_value = left.convert_to_string()?;
-        let right_value = right.convert_to_string()?;
+        Ok(Value::Boolean(left.contains(&right)))
+    }
+}
+
+// https://www.w3.org/TR/xpath-functions-31/#func-contains-token
+// boolean contains-token(string, string)
+// Whitespace separated token membership. ex: contains-token(@class, "btn")
+#[derive(Debug)]
+pub struct ContainsToken;
 
-        Ok(Value::Boolean(match (left_value, right_value) {
-            (left, _) if left.is_empty() => false,
-            (_, right) if right.is_empty() => true,
+impl Function for ContainsToken {
+    fn exec<'a>(&self, eval: &Evaluation, args: Args<'a>) -> Result<Value> {
+        let (input, token) = two_string_args(eval, args)?;
 
-            (left, right) => left.contains(&right),
-        }))
+        let token = token.trim();
+
+        Ok(Value::Boolean(
+            !token.is_empty() && input.split_whitespace().any(|v| v == token),
+        ))
     }
 }
 
+// Both arguments converted to strings. A missing value (ex: an empty node-set) is an empty string.
+// An empty string is at the start, end and inside of every string, including another empty one.
+fn two_string_args(eval: &Evaluation, mut args: Args<'_>) -> Result<(String, String)> {
+    let mut string_arg = |index| -> Result<String> {
+        Ok(match args.get_required_optional_value(index, eval)? {
+            Some(value) => value.convert_to_string()?,
+            None => String::new(),
+        })
+    };
+
+    Ok((string_arg(0)?, string_arg(1)?))
+}
+
 // string substring-before(string, string)
 #[derive(Debug)]
 pub struct SubstringBefore;
@@ -314,26 +385,42 @@ impl Function for Substring {
 
         let value_str = value_0.convert_to_string()?;
 
-        let start = value_1.number()?.round().abs() as isize - 1;
+        let start = round_half_up(value_1.convert_to_number()?);
+
+        // Without a length it continues to the end of the string.
+        let end = match args.get_optional(2) {
+            Some(arg) => {
+                let length = value_or_empty(arg.next_eval(eval)?);
 
-        let end = args
-            .get_optional(2)
-            .and_then(|v| v.next_eval(eval).ok().flatten())
-            .map(|v| v.number())
-            .unwrap_or_else(|| Ok(value_str.len() as f64))?
-            .round() as isize;
+                start + round_half_up(length.convert_to_number()?)
+            }
 
-        let end = start + end;
+            None => f64::INFINITY,
+        };
 
+        // Any NaN fails both comparisons, returning an empty string.
         Ok(Value::String(
-            value_str
-                .get(start.min(0) as usize..end.min(0) as usize)
-                .map(|v| v.to_string())
-                .unwrap_or_default(),
+            char_positions(&value_str)
+                .filter(|&(position, _)| {
+                    let position = position as f64;
+                    position >= start && position < end
+                })
+                .map(|(_, c)| c)
+                .collect(),
         ))
     }
 }
 
+// Strings are indexed by character rather than byte, starting at 1.
+fn char_positions(value: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    value.chars().enumerate().map(|(index, c)| (index + 1, c))
+}
+
+// XPath round(), halves go towards positive infinity.
+fn round_half_up(value: f64) -> f64 {
+    (value + 0.5).floor()
+}
+
 // number string-length(string?)
 #[derive(Debug)]
 pub struct StringLength;
@@ -341,11 +428,9 @@ pub struct StringLength;
 impl Function for StringLength {
     fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
         if let Some(arg) = args.get_optional(0) {
-            let value = arg.next_eval(eval)?.ok_or(Error::UnableToFindValue)?;
-
-            let value_str = value.convert_to_string()?;
+            let value_str = value_or_empty(arg.next_eval(eval)?).convert_to_string()?;
 
-            Ok(Value::Number(value_str.len() as f64))
+            Ok(Value::Number(char_positions(&value_str).count() as f64))
         } else {
             Ok(Value::Number(0.0))
         }
@@ -360,9 +445,7 @@ impl Function for NormalizeSpace {
     fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
         match args.get_optional(0) {
             Some(expr) => {
-                let value = expr.next_eval(eval)?.ok_or(Error::UnableToFindValue)?;
-
-                let value_str = value.convert_to_string()?;
+                let value_str = value_or_empty(expr.next_eval(eval)?).convert_to_string()?;
 
                 Ok(Value::String(
                     value_str
@@ -372,8 +455,9 @@ impl Function for NormalizeSpace {
                             (String::new(), false),
                             |(mut value, mut ignore_spaces), ch| {
                                 if ch.is_whitespace() {
+                                    // Any run of whitespace becomes a single space.
                                     if !ignore_spaces {
-                                        value.push(ch);
+                                        value.push(' ');
                                         ignore_spaces = true;
                                     }
                                 } else {
@@ -396,7 +480,18 @@ impl Function for NormalizeSpace {
 // string translate(string, string, string)
 
 // Boolean Functions
+
 // boolean boolean(object)
+#[derive(Debug)]
+pub struct ToBoolean;
+
+impl Function for ToBoolean {
+    fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
+        // An empty node-set is false.
+        let found = args.get_required_optional_value(0, eval)?;
+        Ok(Value::Boolean(found.is_some_and(Value::convert_to_boolean)))
+    }
+}
 
 // boolean not(boolean)
 #[derive(Debug)]
@@ -404,8 +499,10 @@ pub struct Not;
 
 impl Function for Not {
     fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
-        let found = args.get_required_value(0, eval)?;
-        Ok(Value::Boolean(!found.boolean()?))
+        let found = args.get_required_optional_value(0, eval)?;
+        Ok(Value::Boolean(
+            !found.is_some_and(Value::convert_to_boolean),
+        ))
     }
 }
 
@@ -7,6 +7,7 @@ pub mod functions;
 pub mod nodetest;
 pub mod parser;
 pub mod result;
+mod span;
 pub mod tokens;
 pub mod value;
 
@@ -16,10 +17,13 @@ pub(crate) use parser::Tokenizer;
 pub(crate) use tokens::{AxisName, ExprToken, NodeType, Operator, PrincipalNodeType};
 pub(crate) use value::{Node, Nodeset};
 
+pub use context::EvaluationOptions;
+pub use encoding_rs::Encoding;
 pub use factory::{Document, Factory};
 pub use result::{Error, Result};
 pub use value::Value;
 
+/// Parse an HTML document. The data must be UTF-8.
 pub fn parse_document<R: std::io::Read>(data: &mut R) -> Result<Document> {
     let parse: markup5ever_rcdom::RcDom =
         html5ever::parse_document(markup5ever_rcdom::RcDom::default(), Default::default())
@@ -29,6 +33,86 @@ pub fn parse_document<R: std::io::Read>(data: &mut R) -> Result<Document> {
     Ok(Document::new(parse.document.into()))
 }
 
+/// Parse a document which isn't UTF-8. A byte order mark in the data takes priority over `encoding`.
+pub fn parse_document_with_encoding<R: std::io::Read>(
+    data: &mut R,
+    encoding: &'static Encoding,
+) -> Result<Document> {
+    let mut bytes = Vec::new();
+    data.read_to_end(&mut bytes)?;
+
+    let (text, _, _) = encoding.decode(&bytes);
+
+    let parse: markup5ever_rcdom::RcDom =
+        html5ever::parse_document(markup5ever_rcdom::RcDom::default(), Default::default())
+            .one(&*text);
+
+    Ok(Document::new(parse.document.into()))
+}
+
+/// Parse a document, remembering where each element came from in the source.
+///
+/// See [`Node::source_span`](crate::value::Node::source_span). The data must be UTF-8.
+pub fn parse_document_with_spans<R: std::io::Read>(data: &mut R) -> Result<Document> {
+    let mut source = String::new();
+    data.read_to_string(&mut source)?;
+
+    let (parse, spans) = span::parse(&source);
+
+    let mut document = Document::new(parse.document.into());
+    document.source_spans = spans;
+
+    Ok(document)
+}
+
+/// Parse a document, dropping the whitespace-only text nodes between elements.
+///
+/// Whitespace inside `<pre>` and `<textarea>` is kept. Use [`parse_document`] to keep every node.
+pub fn parse_document_trimmed<R: std::io::Read>(data: &mut R) -> Result<Document> {
+    let parse: markup5ever_rcdom::RcDom =
+        html5ever::parse_document(markup5ever_rcdom::RcDom::default(), Default::default())
+            .from_utf8()
+            .read_from(data)?;
+
+    remove_whitespace_text(&parse.document);
+
+    Ok(Document::new(parse.document.into()))
+}
+
+fn remove_whitespace_text(handle: &markup5ever_rcdom::Handle) {
+    use markup5ever_rcdom::NodeData;
+
+    if let NodeData::Element { name, .. } = &handle.data {
+        if matches!(&*name.local, "pre" | "textarea") {
+            return;
+        }
+    }
+
+    handle
+        .children
+        .borrow_mut()
+        .retain(|child| match &child.data {
+            NodeData::Text { contents } => !contents.borrow().trim().is_empty(),
+            _ => true,
+        });
+
+    for child in handle.children.borrow().iter() {
+        remove_whitespace_text(child);
+    }
+}
+
+/// Parse an XML document. The data must be UTF-8.
+///
+/// Element names keep their case. Their prefixes can be looked up with [`Document::lookup_namespace`].
+pub fn parse_xml_document<R: std::io::Read>(data: &mut R) -> Result<Document> {
+    let parse: markup5ever_rcdom::RcDom =
+        xml5ever::driver::parse_document(markup5ever_rcdom::RcDom::default(), Default::default())
+            .from_utf8()
+            .read_from(data)?;
+
+    Ok(Document::new(parse.document.into()))
+}
+
 pub fn compile_lines(node: &Node) -> String {
     let mut items = Vec::new();
 
@@ -77,18 +161,24 @@ fn node_name(node: &Node) -> String {
 mod tests {
     #![allow(dead_code)]
 
+    use std::cell::Cell;
     use std::io::Cursor;
+    use std::rc::Rc;
 
     use tracing::debug;
 
-    pub use crate::context::Evaluation;
+    pub use crate::context::{Evaluation, EvaluationOptions};
+    use crate::expressions::Step;
     pub use crate::factory::{Document, Factory};
     pub use crate::nodetest::{NameTest, NodeTest};
-    pub use crate::parse_document;
     pub use crate::parser::Tokenizer;
-    pub use crate::result::{Error, Result};
+    pub use crate::result::{Error, Result, ValueError};
     pub use crate::tokens::{AxisName, ExprToken, NodeType, Operator, PrincipalNodeType};
-    pub use crate::value::{Node, Nodeset, Value};
+    pub use crate::value::{Node, NodeKind, Nodeset, Value};
+    pub use crate::{
+        parse_document, parse_document_trimmed, parse_document_with_encoding,
+        parse_document_with_spans, parse_xml_document,
+    };
 
     const WEBPAGE: &str = r#"
 		<!DOCTYPE html>
@@ -178,9 +268,64 @@ mod tests {
         );
     }
 
+    fn first_node(doc: &Document, search: &str) -> Node {
+        evaluate(doc, search).unwrap().unwrap().into_node().unwrap()
+    }
+
+    // The error parsing stopped at, after the part of the query it consumed.
+    fn parse_error(doc: &Document, search: &str) -> (Error, String, String) {
+        match doc.evaluate(search).map(|_| ()) {
+            Err(Error::Parse {
+                error,
+                consumed,
+                remaining,
+            }) => (error.inner().clone(), consumed, remaining),
+            v => panic!("Expected Parse Error {:?}: {:?}", search, v),
+        }
+    }
+
+    // The string-value of each result.
+    fn evaluate_strings(doc: &Document, search: &str) -> Vec<String> {
+        doc.evaluate(search)
+            .unwrap()
+            .map(|v| v.and_then(|v| v.convert_to_string()).unwrap())
+            .collect()
+    }
+
+    // The attribute of each resulting node. Empty when the node doesn't have it.
+    fn attribute_values(doc: &Document, search: &str, attribute: &str) -> Vec<String> {
+        doc.evaluate(search)
+            .unwrap()
+            .nodes()
+            .map(|node| {
+                node.unwrap()
+                    .evaluate_from(format!("string(@{attribute})"), doc)
+                    .unwrap()
+                    .next()
+                    .unwrap()
+                    .and_then(|v| v.convert_to_string())
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    // Counts how many nodes it's asked about. Matches anchors.
+    #[derive(Debug)]
+    struct CountingTest(Rc<Cell<usize>>);
+
+    impl NodeTest for CountingTest {
+        fn test(&self, context: &Evaluation, result: &mut Nodeset) {
+            self.0.set(self.0.get() + 1);
+
+            if context.node.tag_name().as_deref() == Some("a") {
+                result.add_node(context.node.clone());
+            }
+        }
+    }
+
     fn assert_eq_err(doc: &Document, search: &str, value: Error) {
         assert_eq!(
-            evaluate(doc, search),
+            evaluate(doc, search).map(|v| v.map_err(|e| e.inner().clone())),
             Some(Err(value)),
             "Eval EQ ERR {:?}",
             search
@@ -194,6 +339,9 @@ mod tests {
         // Simple
         assert_eq_eval(&doc, r#"1 + 1"#, 2.0);
         assert_eq_eval(&doc, r#"0 - 2"#, -2.0);
+        assert_eq_eval(&doc, r#"1 + 2 + 3"#, 6.0);
+        assert_eq_eval(&doc, r#"1 - 2 + 3"#, 2.0);
+        assert_eq_eval(&doc, r#"1 + 2 * 3 - 4"#, 3.0);
 
         assert_eq_eval(&doc, r#"-2"#, -2.0);
 
@@ -252,6 +400,51 @@ mod tests {
             "clickable1",
         );
 
+        // == Descendant shorthand ==
+
+        assert_eq_count(&doc, r#"//a"#, 4);
+        assert_eq_count(&doc, r#"//div/a"#, 2);
+        assert_eq_count(&doc, r#"//body/a"#, 2);
+        // The first anchor child of each parent.
+        assert_eq_count(&doc, r#"//a[1]"#, 3);
+        assert_eq_count(&doc, r#"//body/a[1]"#, 1);
+        // The root is never a child so '//node()' can't include it.
+        assert_eq_count(&doc, r#"//node()"#, 52);
+        assert_eq_count(&doc, r#"/html//html"#, 0);
+
+        let nested = parse_document(&mut Cursor::new(
+            r#"<div id="a"><div id="b"><div id="c"></div></div><div id="d"></div></div>"#,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            nested
+                .evaluate_all(r#"//div[last()]/@id"#)
+                .unwrap()
+                .into_iter()
+                .map(|v| v.convert_to_string())
+                .collect::<Result<Vec<_>>>(),
+            Ok(vec![
+                String::from("a"),
+                String::from("c"),
+                String::from("d")
+            ])
+        );
+
+        // == Unabbreviated axes from the root ==
+
+        assert_eq_count(&doc, r#"child::html"#, 1);
+        assert_eq_count(&doc, r#"child::node()"#, 2);
+        assert_eq_count(&doc, r#"descendant::div"#, 3);
+        assert_eq_count(&doc, r#"descendant-or-self::node()"#, 53);
+        assert_eq!(
+            doc.evaluate(r#"descendant-or-self::node()"#)
+                .unwrap()
+                .next()
+                .map(|v| v.and_then(|v| v.into_node()).map(|n| n.is_root())),
+            Some(Ok(true))
+        );
+
         debug!("Location Paths (Unabbreviated Syntax)");
         // assert_eq!(doc.evaluate("//head/title"), Ok(Value::Nodeset(vec![].into()))); // selects the document root (which is always the parent of the document element)
         // dbg!(doc.evaluate("self::para")); // selects the context node if it is a para element, and otherwise selects nothing
@@ -316,69 +509,3288 @@ mod tests {
     }
 
     #[test]
-    fn general_examples() {
+    fn string_values() {
+        let doc = parse_document(&mut Cursor::new("<p>a<b>c</b>d</p>")).unwrap();
+
+        // Text descendants are concatenated in document order without separators.
+        assert_eq_eval_to_string(&doc, r#"//p"#, "acd");
+        assert_eq_eval_to_string(&doc, r#"//b"#, "c");
+        assert_eq_count(&doc, r#"//p[contains(., "acd")]"#, 1);
+        assert_eq_count(&doc, r#"//p[contains(., "a c")]"#, 0);
+    }
+
+    #[test]
+    fn document_encoding() {
+        // "Café Noël" in Latin-1.
+        let latin1: &[u8] = b"<html><body><p>Caf\xe9 No\xebl</p></body></html>";
+
+        let doc = parse_document_with_encoding(&mut Cursor::new(latin1), encoding_rs::WINDOWS_1252)
+            .unwrap();
+
+        assert_eq_eval_to_string(&doc, r#"//p"#, "Café Noël");
+
+        // A BOM overrides the given encoding.
+        let mut utf8 = vec![0xEF, 0xBB, 0xBF];
+        utf8.extend_from_slice("<p>Café</p>".as_bytes());
+
+        let doc = parse_document_with_encoding(&mut Cursor::new(utf8), encoding_rs::WINDOWS_1252)
+            .unwrap();
+
+        assert_eq_eval_to_string(&doc, r#"//p"#, "Café");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_values() {
+        use crate::value::AsHtml;
+
         let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
 
-        // Simple
+        let to_json = |value: &Value| serde_json::to_string(value).unwrap();
 
-        assert_eq_eval(&doc, r#"contains("abc123", "bc12")"#, true);
-        assert_eq_eval(&doc, r#"contains("abc123", "4")"#, false);
+        assert_eq!(to_json(&Value::Boolean(true)), "true");
+        assert_eq!(to_json(&Value::Number(1.5)), "1.5");
+        assert_eq!(to_json(&Value::String(String::from("a\"b"))), r#""a\"b""#);
 
-        assert_eq_eval(&doc, r#"concat(true, "123")"#, Value::String("123".into()));
-        assert_eq_eval(&doc, r#"concat(false, "123")"#, Value::String("123".into()));
-        assert_eq_eval(&doc, r#"concat(1, "123")"#, Value::String("1123".into()));
-        assert_eq_eval(
-            &doc,
-            r#"concat("abc", "123")"#,
-            Value::String("abc123".into()),
+        let div = evaluate(&doc, r#"//div"#).unwrap().unwrap();
+        assert_eq!(to_json(&div), r#""Testing 1""#);
+        assert_eq!(
+            serde_json::to_string(&AsHtml(&div)).unwrap(),
+            r#""<div class=\"test1\">Testing 1</div>""#
         );
 
-        // TODO: Below doesn't work.
+        let attrs = Value::from(
+            doc.evaluate(r#"//div/@class"#)
+                .unwrap()
+                .nodes()
+                .collect::<Result<Vec<Node>>>()
+                .unwrap(),
+        );
+        assert_eq!(to_json(&attrs), r#"["test1","group1","group2"]"#);
 
-        // assert_eq_eval(&doc, r#"starts-with("abc123", "abc")"#, true);
-        // assert_eq_eval(&doc, r#"starts-with("123", 1)"#, true);
+        // Non-node values aren't affected.
+        assert_eq!(
+            serde_json::to_string(&AsHtml(&Value::Number(2.0))).unwrap(),
+            "2.0"
+        );
+    }
 
-        // assert_eq_eval(&doc, r#"substring-before("abc123", "1")"#, Value::String("abc".into()));
+    #[test]
+    fn evaluate_all() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
 
-        // assert_eq_eval(&doc, r#"substring-after("abc123", "c")"#, Value::String("123".into()));
+        let classes = doc
+            .evaluate_all(r#"//div/@class"#)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.convert_to_string())
+            .collect::<Result<Vec<_>>>();
 
-        // Document Lookups
+        assert_eq!(
+            classes,
+            Ok(vec![
+                String::from("test1"),
+                String::from("group1"),
+                String::from("group2")
+            ])
+        );
 
-        assert_eq_eval(
-            &doc,
-            r#"//div[contains(text(), "Testing 1")]/@class"#,
-            Value::String("test1".into()),
+        assert_eq!(doc.evaluate_all(r#"1 + 1"#), Ok(vec![Value::Number(2.0)]));
+        assert_eq!(doc.evaluate_all(r#"//video"#), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn query_endings() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        // Each of these used to index past the end of the query.
+        for search in [
+            r#"//div/"#,
+            r#"//div/ "#,
+            r#"//div//"#,
+            r#"//div["#,
+            r#"//div[@"#,
+            r#"""#,
+            r#"'"#,
+            r#"1 +"#,
+            r#"1 + "#,
+            "",
+            "   ",
+        ] {
+            assert!(doc.evaluate(search).is_err(), "IS ERR {search:?}");
+        }
+
+        assert_eq_count(&doc, r#"//div "#, 3);
+        assert_eq_count(&doc, r#"  //div  "#, 3);
+        assert_eq_eval(&doc, r#"1 + 1 "#, Value::Number(2.0));
+        assert_eq_eval_to_string(&doc, r#"string(//title) "#, "Document");
+
+        let mut tokenizer = Tokenizer::new(r#"a  "#);
+        assert!(tokenizer.next().is_some());
+        assert!(tokenizer.is_finished());
+        assert!(tokenizer.next().is_none());
+        assert_eq!(Tokenizer::new("").next_token(), Err(Error::Token));
+        assert_eq!(Tokenizer::new("  ").next_token(), Err(Error::Token));
+    }
+
+    #[test]
+    fn tokenizer_reset() {
+        let mut tokenizer = Tokenizer::new(r#"//a"#);
+
+        assert_eq!(
+            tokenizer.by_ref().collect::<Result<Vec<_>>>(),
+            Ok(vec![
+                ExprToken::Operator(Operator::DoubleForwardSlash),
+                ExprToken::NameTest(NameTest {
+                    prefix: None,
+                    local_part: "a".into()
+                }),
+            ])
         );
+        assert!(tokenizer.is_finished());
 
-        // debug!("Examples");
-        // dbg!(doc.evaluate("//*[@id='rcTEST']//*[contains(text(), 'TEST Interactive')]/../button[2]"));
-        // dbg!(doc.evaluate("//*[@id='rcTEST']//*[contains(text(), 'TEST Interactive')]/..//*[contains(text(), 'Setting')]"));
-        // dbg!(doc.evaluate("//*[@id='rcTEST']//*[contains(text(), 'TEST Interactive')]/following-sibling::button"));
-        // dbg!(doc.evaluate("// address[@class='ng-scope ng-isolate-scope']//div[contains('Testing') and @id='msgTitle']"));
-        // dbg!(doc.evaluate("//*[@name='myForm']//table[@id='tbl_ testdm']/tbody/tr/td[6]/"));
-        // dbg!(doc.evaluate("input[@value='Open RFS']"));
-        // dbg!(doc.evaluate("//*[@title='Songs List Applet']//table//td[contains(text(),'Author')]"));
-        // dbg!(doc.evaluate("//*[@id='parameters']//*[@id='testUpdateTime']"));
-        // dbg!(doc.evaluate("//*[@id='MODEL/PLAN']/div[1]/div[2]/div[1]/div[1]/widget/section/div[1]/div/div[1]/div/div/button[1]"));
-        // dbg!(doc.evaluate("//*[contains(text(),'Watch Dial')]/../div/select[@data-ng-model='context.questions[subqts.subHandleSubId]']"));
-        // dbg!(doc.evaluate("//*[@id='RESEARCH/PLAN']//*[contains(@id, 'A4')]/../../following-sibling::div[1]/div[1]/span[1]/span[1]"));
-        // dbg!(doc.evaluate("//*[@id='ALARMDATA']//*[contains(@id, 'AFC2')]/../../preceding-sibling::div[1]/div[1]/span[1]/span[1]"));
-        // dbg!(doc.evaluate("//*[@id='RESEARCH/REVIEW']//widget/section/div[1]/div/div[2]/div[1]/div[3]/div[1]//span[@class='details']"));
-        // dbg!(doc.evaluate("//a[contains(.,'Parameter Data Manual Entry')]"));
-        // dbg!(doc.evaluate("//*[contains(@style,'display: block; top:')]//input[@name='daterangepicker_end']"));
-        // dbg!(doc.evaluate("//*[@id='dropdown-filter-serviceTools']/following-sibling::ul/descendant::a[text()='Notepad']"));
-        // dbg!(doc.evaluate("//*[@id='dropdown-filter-serviceTools']/following-sibling::ul/descendant::a[text()='Trigger Dashboard']"));
-        // dbg!(doc.evaluate("//h3[text()='Internal Debrief']"));
-        // dbg!(doc.evaluate("//h3[contains(text(),'Helium Level')]/following-sibling::div/label/input"));
-        // dbg!(doc.evaluate("//div[div[p[contains(text(),'Status')]]]/preceding-sibling::div/div/span[3]/span"));
-        // dbg!(doc.evaluate("//*[@id='COUPLING']//*[contains(text(),'COUPLE Trend')]/../div/select"));
-        // dbg!(doc.evaluate("//*[@id='ffaHeaderDropdown']//a[contains(text(),'Start Workflow')]"));
+        tokenizer.reset(r#"1 * 2"#);
+
+        assert_eq!(tokenizer.query(), "1 * 2");
+        assert_eq!(tokenizer.position(), 0);
+        assert_eq!(
+            tokenizer.collect::<Result<Vec<_>>>(),
+            Ok(vec![
+                ExprToken::Number(1.0),
+                ExprToken::Operator(Operator::Star),
+                ExprToken::Number(2.0),
+            ])
+        );
+
+        // The previous query's last token doesn't make this '*' a multiplication.
+        let mut tokenizer = Tokenizer::new(r#"//a"#);
+        tokenizer.by_ref().for_each(drop);
+        tokenizer.reset(r#"*"#);
+
+        assert_eq!(
+            tokenizer.collect::<Result<Vec<_>>>(),
+            Ok(vec![ExprToken::NameTest(NameTest {
+                prefix: None,
+                local_part: "*".into()
+            })])
+        );
     }
 
     #[test]
-    fn general_errors() {
-        // let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+    fn star_tokens() {
+        let star_tokens = |search: &str| {
+            Tokenizer::new(search)
+                .filter_map(|t| match t.unwrap() {
+                    ExprToken::Operator(Operator::Star) => Some("multiply"),
+                    ExprToken::NameTest(NameTest { local_part, .. }) if local_part == "*" => {
+                        Some("wildcard")
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
 
-        // assert_eq_err(&doc, r#"contains("abc123")"#, Error::FunctionError("alloc::boxed::Box<dyn xpather::functions::Function>".to_string(), Box::new(Error::MissingFuncArgument)));
+        assert_eq!(star_tokens(r#"//a/*"#), vec!["wildcard"]);
+        assert_eq!(star_tokens(r#"count(*) * 2"#), vec!["wildcard", "multiply"]);
+        assert_eq!(star_tokens(r#"a[1] * 2"#), vec!["multiply"]);
+        assert_eq!(
+            star_tokens(r#"@* * *"#),
+            vec!["wildcard", "multiply", "wildcard"]
+        );
+
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_count(&doc, r#"//a/*"#, 1);
+        assert_eq_count(&doc, r#"/html/body/*"#, 7);
+        // Predicates after a wildcard step are kept.
+        assert_eq_count(&doc, r#"/html/body/*[2]"#, 1);
+        assert_eq_eval(&doc, r#"count(*) * 2"#, Value::Number(2.0));
+        assert_eq_eval(&doc, r#"count(//div) * count(//img)"#, Value::Number(6.0));
+        assert_eq_eval(&doc, r#"2 * 3 + 1"#, Value::Number(7.0));
+    }
+
+    #[test]
+    fn division() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_eval(&doc, r#"6 div 4"#, Value::Number(1.5));
+        assert_eq_eval(&doc, r#"8 div 4 div 2"#, Value::Number(1.0));
+        assert_eq_eval(&doc, r#"2 * 3 div 4"#, Value::Number(1.5));
+        assert_eq_eval(&doc, r#"5 mod 2"#, Value::Number(1.0));
+        assert_eq_eval(&doc, r#"5 mod -2"#, Value::Number(1.0));
+        assert_eq_eval(&doc, r#"-5 mod 2"#, Value::Number(-1.0));
+        assert_eq_eval(&doc, r#"count(//div) div 3 + 1"#, Value::Number(2.0));
+
+        // IEEE 754 rather than a panic.
+        assert_eq_eval_to_string(&doc, r#"1 div 0"#, "Infinity");
+        assert_eq_eval_to_string(&doc, r#"-1 div 0"#, "-Infinity");
+        assert_eq_eval_to_string(&doc, r#"0 div 0"#, "NaN");
+        assert_eq_eval_to_string(&doc, r#"1 mod 0"#, "NaN");
+        assert_eq_eval(&doc, r#"1 div 0 > 1000000"#, true);
+        assert_eq_eval(&doc, r#"-1 div 0 < 0"#, true);
+        assert_eq_eval(&doc, r#"0 div 0 = 0 div 0"#, false);
+        assert_eq_eval(&doc, r#"0 div 0 != 0 div 0"#, true);
+        assert_eq_eval(&doc, r#"1 div 0 = 2 div 0"#, true);
+
+        // Element names unless they follow an operand.
+        assert_eq_count(&doc, r#"/html/body/div[1]/following::div"#, 2);
+        assert_eq_count(&doc, r#"//div[div]"#, 0);
+        assert_eq_eval(&doc, r#"count(//div) mod 2"#, Value::Number(1.0));
+
+        // Operands are converted with number(). A node-set is its first node's string-value.
+        let numbers = parse_document(&mut Cursor::new(r#"<p>6</p><p>4</p><p>six</p>"#)).unwrap();
+
+        assert_eq_eval(&numbers, r#"//p[1] div 2"#, Value::Number(3.0));
+        assert_eq_eval(&numbers, r#"//p div //p[2]"#, Value::Number(1.5));
+        assert_eq_eval(&numbers, r#"//p[1] * //p[2]"#, Value::Number(24.0));
+        assert_eq_eval(&numbers, r#"//p[1] mod //p[2]"#, Value::Number(2.0));
+        assert_eq_eval(&numbers, r#"//p[2] + 1 - //p[1]"#, Value::Number(-1.0));
+        assert_eq_eval(&numbers, r#"'6' mod 4"#, Value::Number(2.0));
+        assert_eq_eval(&numbers, r#"' 6 ' div '4'"#, Value::Number(1.5));
+        assert_eq_eval(&numbers, r#"true() * 3"#, Value::Number(3.0));
+        assert_eq_eval_to_string(&numbers, r#"//p[3] * 2"#, "NaN");
+        assert_eq_eval_to_string(&numbers, r#"'six' div 2"#, "NaN");
+        assert_eq_eval_to_string(&numbers, r#"//missing mod 2"#, "NaN");
+    }
+
+    #[test]
+    fn produce_nodes() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let nodes = doc
+            .evaluate(r#"//div"#)
+            .unwrap()
+            .nodes()
+            .collect::<Result<Vec<Node>>>()
+            .unwrap();
+
+        assert_eq!(nodes.len(), 3);
+        assert!(nodes.iter().all(|n| n.is_element()));
+
+        assert_eq!(
+            doc.evaluate(r#"1 + 1"#)
+                .unwrap()
+                .nodes()
+                .collect::<Result<Vec<Node>>>()
+                .map(|v| v.len()),
+            Err(Error::InvalidValue(ValueError::Nodeset))
+        );
+    }
+
+    #[test]
+    fn produce_nodes_with_text() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let pairs = doc
+            .evaluate(r#"//div"#)
+            .unwrap()
+            .nodes_with_text()
+            .collect::<Result<Vec<(Node, String)>>>()
+            .unwrap();
+
+        assert_eq!(pairs.len(), 3);
+        assert!(pairs[0].0.is_element());
+        assert_eq!(pairs[0].1, "Testing 1");
+
+        assert_eq!(
+            doc.evaluate(r#"//div/@class"#)
+                .unwrap()
+                .nodes_with_text()
+                .map(|v| v.map(|(_, text)| text))
+                .collect::<Result<Vec<String>>>(),
+            Ok(vec![
+                String::from("test1"),
+                String::from("group1"),
+                String::from("group2")
+            ])
+        );
+    }
+
+    #[test]
+    fn siblings() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let element_names = |nodes: Vec<Node>| {
+            nodes
+                .into_iter()
+                .filter_map(|n| n.name())
+                .map(|n| n.local.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let div = first_node(&doc, r#"//div[@class="test1"]"#);
+
+        assert_eq!(
+            element_names(div.following_siblings()),
+            vec!["span", "span", "a", "div", "a", "div"]
+        );
+        assert!(element_names(div.preceding_siblings()).is_empty());
+        // Whitespace text before the div.
+        assert!(div.preceding_siblings().iter().all(|n| n.is_text()));
+
+        let last = first_node(&doc, r#"//div[@class="group2"]"#);
+
+        assert_eq!(
+            element_names(last.preceding_siblings()),
+            vec!["div", "span", "span", "a", "div", "a"]
+        );
+
+        let attr = first_node(&doc, r#"//div/@class"#);
+
+        assert!(attr.following_siblings().is_empty());
+    }
+
+    #[test]
+    fn function_nodesets() {
+        use crate::expressions::{Expression, Function};
+        use crate::functions::{self, Args};
+
+        // Returns the first two div elements.
+        #[derive(Debug)]
+        struct TwoDivs;
+
+        impl functions::Function for TwoDivs {
+            fn exec<'a>(&self, eval: &Evaluation, _: Args<'a>) -> Result<Value> {
+                let nodes = eval
+                    .document
+                    .evaluate(r#"//div"#)?
+                    .nodes()
+                    .take(2)
+                    .collect::<Result<Vec<Node>>>()?;
+
+                Ok(Value::from(nodes))
+            }
+        }
+
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+        let eval = Evaluation::new(&doc.root, &doc);
+
+        let value = functions::Function::exec(&TwoDivs, &eval, Args::new(&mut [])).unwrap();
+        assert!(value.is_nodeset());
+        assert!(value.is_something());
+        assert_eq!(value.as_nodeset().map(|v| v.len()), Ok(2));
+        assert_eq!(value.to_string(), "Testing 1");
+
+        let mut count = Function::new(
+            Box::new(functions::Count),
+            vec![Box::new(Function::new(Box::new(TwoDivs), Vec::new()))],
+        );
+
+        assert_eq!(count.next_eval(&eval), Ok(Some(Value::Number(2.0))));
+
+        assert_eq!(
+            Value::from(Vec::<Node>::new()).convert_to_string(),
+            Ok(String::new())
+        );
+    }
+
+    #[test]
+    fn value_conversions() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+        let div = doc.evaluate_unique(r#"//div[1]"#).unwrap().unwrap();
+
+        // Into a value and back again.
+        assert_eq!(
+            String::try_from(Value::from(String::from("a"))),
+            Ok("a".into())
+        );
+        assert_eq!(f64::try_from(Value::from(1.5)), Ok(1.5));
+        assert_eq!(bool::try_from(Value::from(true)), Ok(true));
+        assert_eq!(Node::try_from(Value::from(div.clone())), Ok(div.clone()));
+
+        // Coerced into the type.
+        let string: Result<String> = Value::from(div.clone()).try_into();
+        assert_eq!(string, Ok("Testing 1".into()));
+        assert_eq!(String::try_from(Value::from(2.0)), Ok("2".into()));
+        assert_eq!(String::try_from(Value::from(false)), Ok("false".into()));
+        assert_eq!(f64::try_from(Value::from(true)), Ok(1.0));
+        assert_eq!(bool::try_from(Value::from(String::new())), Ok(false));
+        assert_eq!(bool::try_from(Value::from(div.clone())), Ok(true));
+
+        // Types which don't convert.
+        assert_eq!(
+            f64::try_from(Value::from(String::from("1"))),
+            Err(Error::InvalidValue(ValueError::Number))
+        );
+        assert_eq!(
+            f64::try_from(Value::from(div)),
+            Err(Error::InvalidValue(ValueError::Number))
+        );
+        assert_eq!(
+            Node::try_from(Value::from(String::from("a"))),
+            Err(Error::InvalidValue(ValueError::Nodeset))
+        );
+    }
+
+    #[test]
+    fn document_stats() {
+        // The parser adds html, head and body around the paragraph.
+        let doc = parse_document(&mut Cursor::new(r#"<p>a<b>c</b></p>"#)).unwrap();
+
+        // root, html, head, body, p, "a", b, "c"
+        assert_eq!(doc.node_count(), 8);
+        // root > html > body > p > b > "c"
+        assert_eq!(doc.max_depth(), 5);
+
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq!(doc.node_count(), 53);
+        assert_eq!(
+            doc.evaluate(r#"descendant-or-self::node()"#)
+                .unwrap()
+                .count(),
+            doc.node_count()
+        );
+        // root > html > body > div > h1 > text
+        assert_eq!(doc.max_depth(), 5);
+    }
+
+    #[test]
+    fn evaluate_unique() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let title = doc.evaluate_unique(r#"//title"#).unwrap().unwrap();
+        assert_eq!(title.tag_name(), Some(String::from("title")));
+
+        let group = doc
+            .evaluate_unique(r#"//div[@class="group2"]"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            group.value().map(|v| v.to_string().trim().to_string()),
+            Ok(String::from("Open Here!"))
+        );
+
+        assert_eq!(doc.evaluate_unique(r#"//table"#), Ok(None));
+        assert_eq!(doc.evaluate_unique(r#"//div"#), Err(Error::MultipleResults));
+        // Not a node.
+        assert_eq!(
+            doc.evaluate_unique(r#"1 + 1"#),
+            Err(Error::InvalidValue(ValueError::Nodeset))
+        );
+    }
+
+    #[test]
+    fn sibling_axes() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        // The next anchor sibling.
+        assert_eq_eval_to_string(
+            &doc,
+            r#"//div[@class="test1"]/following-sibling::a[1]"#,
+            "Maybe",
+        );
+        assert_eq_count(&doc, r#"//div[@class="test1"]/following-sibling::a[1]"#, 1);
+        assert_eq_eval_to_string(
+            &doc,
+            r#"//div[@class="test1"]/following-sibling::a[position()=1]"#,
+            "Maybe",
+        );
+        assert_eq_eval_to_string(
+            &doc,
+            r#"//div[@class="test1"]/following-sibling::*[1]/@class"#,
+            "test2",
+        );
+
+        // The node test is applied to siblings.
+        assert_eq_count(&doc, r#"//div[@class="test1"]/following-sibling::a"#, 2);
+        assert_eq_count(&doc, r#"//div[@class="test1"]/following-sibling::div"#, 2);
+        assert_eq_count(&doc, r#"//div[@class="group1"]/preceding-sibling::span"#, 2);
+        assert_eq_count(&doc, r#"//div[@class="group1"]/preceding-sibling::*"#, 4);
+        // Both spans have the same next anchor.
+        assert_eq_count(&doc, r#"//span/following-sibling::a[1]"#, 1);
+
+        // Attributes have no siblings.
+        assert_eq_count(&doc, r#"//div/@class/following-sibling::node()"#, 0);
+        assert_eq_count(&doc, r#"//div/@class/preceding-sibling::node()"#, 0);
+    }
+
+    #[test]
+    fn following_preceding_skip_attributes() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        for search in [
+            r#"//h1/following::node()"#,
+            r#"//div[@class="group2"]/preceding::node()"#,
+            r#"//*/following::node()"#,
+            r#"//*/preceding::node()"#,
+            r#"//div/@class/following::node()"#,
+            r#"//div/@class/preceding::node()"#,
+        ] {
+            let nodes = doc
+                .evaluate(search)
+                .unwrap()
+                .nodes()
+                .collect::<Result<Vec<Node>>>()
+                .unwrap();
+
+            assert!(!nodes.is_empty(), "{search}");
+            assert!(
+                nodes.iter().all(|n| !n.is_attribute() && !n.is_namespace()),
+                "{search}"
+            );
+        }
+
+        assert_eq_count(&doc, r#"//h1/following::node()[self::a]"#, 3);
+        assert_eq_count(&doc, r#"//body/*[1]/following::*/@class"#, 7);
+    }
+
+    #[test]
+    fn reverse_axes() {
+        let doc = parse_document(&mut Cursor::new(
+            r#"<div id="outer"><div id="middle"><p id="first">1</p><p id="second">2</p><div id="inner"><a>x</a></div></div></div>"#,
+        ))
+        .unwrap();
+
+        // Nearest first along the axis, results in document order.
+        assert_eq!(
+            attribute_values(&doc, r#"//a/ancestor::div"#, "id"),
+            ["outer", "middle", "inner"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"//a/ancestor::div[1]"#, "id"),
+            ["inner"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"//a/ancestor::div[last()]"#, "id"),
+            ["outer"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"//a/ancestor::div[position() < 3]"#, "id"),
+            ["middle", "inner"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"//a/ancestor-or-self::*[2]"#, "id"),
+            ["inner"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"//div[@id="inner"]/preceding-sibling::p[1]"#, "id"),
+            ["second"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"//a/preceding::p[1]"#, "id"),
+            ["second"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"//a/preceding::p"#, "id"),
+            ["first", "second"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"//p[@id="second"]/following-sibling::*[1]"#, "id"),
+            ["inner"]
+        );
+
+        assert_eq!(
+            doc.evaluate(r#"//a/ancestor::div"#)
+                .unwrap()
+                .take_nodes(1)
+                .unwrap()
+                .len(),
+            1
+        );
+        // html and body, which have no id, and the divs.
+        assert_eq_count(&doc, r#"//a/ancestor::*"#, 5);
+    }
+
+    #[test]
+    fn evaluate_many() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let counts = doc
+            .evaluate_many(&[r#"//div"#, r#"//img"#, r#"//a"#, r#"//div["#])
+            .into_iter()
+            .map(|v| v.map(|v| v.len()).map_err(|e| e.inner().clone()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            counts,
+            vec![Ok(3), Ok(2), Ok(4), Err(Error::ExpectedClosingBracket)]
+        );
+    }
+
+    #[test]
+    fn dropped_document() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let node = evaluate(&doc, r#"//div"#)
+            .unwrap()
+            .and_then(|v| v.into_node())
+            .unwrap();
+
+        drop(doc);
+
+        assert_eq!(format!("{:?}", node), "Node(<dropped>)");
+    }
+
+    #[test]
+    fn namespaces() {
+        let doc = parse_xml_document(&mut Cursor::new(
+            r#"<root xmlns:x="urn:test"><x:item>Value</x:item></root>"#,
+        ))
+        .unwrap();
+
+        assert_eq!(doc.lookup_namespace("x"), Some(String::from("urn:test")));
+        assert_eq!(doc.lookup_namespace("y"), None);
+
+        // Declared at the bottom of a deep document.
+        let depth = 5_000;
+        let deep = parse_xml_document(&mut Cursor::new(
+            "<a>".repeat(depth) + r#"<z:b xmlns:z="urn:deep"/>"# + &"</a>".repeat(depth),
+        ))
+        .unwrap();
+
+        assert_eq!(deep.lookup_namespace("z"), Some(String::from("urn:deep")));
+
+        let item = evaluate(&doc, r#"//item"#)
+            .unwrap()
+            .and_then(|v| v.into_node())
+            .unwrap();
+
+        assert_eq!(
+            item.lookup_namespace_uri("x"),
+            Some(String::from("urn:test"))
+        );
+        assert_eq!(item.lookup_prefix("urn:test"), Some(String::from("x")));
+        assert_eq!(item.lookup_prefix("urn:other"), None);
+    }
+
+    #[test]
+    fn xml_cdata() {
+        let doc = parse_xml_document(&mut Cursor::new(
+            r#"<root><x><![CDATA[hello]]></x><y>a <![CDATA[<b>bold</b>]]> c</y></root>"#,
+        ))
+        .unwrap();
+
+        // A CDATA section is text, its markup isn't parsed.
+        assert_eq_count(&doc, r#"//x/text()"#, 1);
+        assert_eq_eval_to_string(&doc, r#"//x/text()"#, "hello");
+        assert_eq_eval_to_string(&doc, r#"string(//x)"#, "hello");
+        assert_eq_count(&doc, r#"//y/*"#, 0);
+        assert_eq_count(&doc, r#"//b"#, 0);
+
+        // It joins the text around it.
+        assert_eq_count(&doc, r#"//y/text()"#, 1);
+        assert_eq_eval_to_string(&doc, r#"string(//y)"#, "a <b>bold</b> c");
+        assert_eq_eval(&doc, r#"contains(/root, 'hello')"#, true);
+    }
+
+    #[test]
+    fn processing_instruction_targets() {
+        let pi_token = |search: &str| {
+            Tokenizer::new(search)
+                .find(|t| !matches!(t, Ok(ExprToken::Operator(_))))
+                .unwrap()
+        };
+
+        assert_eq!(
+            pi_token(r#"//processing-instruction()"#),
+            Ok(ExprToken::NodeType(NodeType::ProcessingInstruction(None)))
+        );
+        assert_eq!(
+            pi_token(r#"//processing-instruction("a-b")"#),
+            Ok(ExprToken::NodeType(NodeType::ProcessingInstruction(Some(
+                "a-b".into()
+            ))))
+        );
+        assert_eq!(
+            pi_token(r#"//processing-instruction( 'a-b' )"#),
+            Ok(ExprToken::NodeType(NodeType::ProcessingInstruction(Some(
+                "a-b".into()
+            ))))
+        );
+        assert_eq!(
+            pi_token(r#"//processing-instruction(garbage)"#),
+            Err(Error::Token)
+        );
+        assert_eq!(
+            pi_token(r#"//processing-instruction("a" "b")"#),
+            Err(Error::Token)
+        );
+
+        let doc = parse_xml_document(&mut Cursor::new(
+            r#"<root><?a-b one?><?other two?><item/></root>"#,
+        ))
+        .unwrap();
+
+        assert_eq_count(&doc, r#"//processing-instruction()"#, 2);
+        assert_eq_count(&doc, r#"//processing-instruction("a-b")"#, 1);
+        assert_eq_count(&doc, r#"//processing-instruction("missing")"#, 0);
+        assert!(doc
+            .evaluate(r#"//processing-instruction(garbage)"#)
+            .is_err());
+    }
+
+    #[test]
+    fn unsupported_axes() {
+        let doc = parse_xml_document(&mut Cursor::new(
+            r#"<root xmlns:x="urn:test"><x:item/></root>"#,
+        ))
+        .unwrap();
+
+        for search in [
+            r#"//item/namespace::*"#,
+            r#"namespace::node()"#,
+            r#"count(//*[namespace::x])"#,
+        ] {
+            assert_eq!(
+                doc.evaluate(search).err().map(|e| e.inner().clone()),
+                Some(Error::UnsupportedAxis(AxisName::Namespace)),
+                "{search}"
+            );
+        }
+
+        assert_eq_count(&doc, r#"//item/self::*"#, 1);
+    }
+
+    #[test]
+    fn evaluation_options() {
+        let doc = parse_document(&mut Cursor::new(
+            "<body><!-- note --><p>a</p><?pi target?></body>",
+        ))
+        .unwrap();
+
+        let options = EvaluationOptions {
+            skip_comments_and_pis: true,
+        };
+
+        assert_eq_count(&doc, r#"//body/node()"#, 3);
+        assert_eq!(
+            doc.evaluate_with_options(r#"//body/node()"#, options)
+                .map(|v| v.count()),
+            Ok(1)
+        );
+        // Explicit comment tests still find them.
+        assert_eq!(
+            doc.evaluate_with_options(r#"//body/comment()"#, options)
+                .map(|v| v.count()),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn value_to_string() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let to_string = |value: Value| value.convert_to_string().unwrap();
+
+        assert_eq!(to_string(Value::Boolean(true)), "true");
+        assert_eq!(to_string(Value::Boolean(false)), "false");
+
+        assert_eq!(to_string(Value::Number(1.0)), "1");
+        assert_eq!(to_string(Value::Number(0.5)), "0.5");
+        assert_eq!(to_string(Value::Number(-2.25)), "-2.25");
+        assert_eq!(to_string(Value::Number(-0.0)), "0");
+        assert_eq!(to_string(Value::Number(1e21)), "1000000000000000000000");
+        assert_eq!(to_string(Value::Number(f64::NAN)), "NaN");
+        assert_eq!(to_string(Value::Number(f64::INFINITY)), "Infinity");
+        assert_eq!(to_string(Value::Number(f64::NEG_INFINITY)), "-Infinity");
+
+        assert_eq!(to_string(Value::String("abc".into())), "abc");
+
+        let div = evaluate(&doc, r#"//div"#).unwrap().unwrap();
+        assert_eq!(to_string(div), "Testing 1");
+    }
+
+    #[test]
+    fn number_formatting() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_eval(&doc, r#"string(1)"#, Value::String("1".into()));
+        assert_eq_eval(&doc, r#"string(1.5)"#, Value::String("1.5".into()));
+        assert_eq_eval(&doc, r#"string(-2)"#, Value::String("-2".into()));
+        assert_eq_eval(&doc, r#"string(1 + A)"#, Value::String("NaN".into()));
+        // As many digits as needed to uniquely distinguish the number.
+        assert_eq_eval(
+            &doc,
+            r#"string(0.1 + 0.2)"#,
+            Value::String("0.30000000000000004".into()),
+        );
+        assert_eq_eval(&doc, r#"concat(2.5, "!")"#, Value::String("2.5!".into()));
+        assert_eq_eval(&doc, r#"string(//div)"#, Value::String("Testing 1".into()));
+
+        assert_eq!(Value::Number(3.0).to_string(), "3");
+        assert_eq!(Value::Number(0.25).to_string(), "0.25");
+        assert_eq!(Value::Number(-0.0).to_string(), "0");
+        assert_eq!(Value::Number(f64::NAN).to_string(), "NaN");
+        assert_eq!(Value::Number(f64::NEG_INFINITY).to_string(), "-Infinity");
+        assert_eq!(Value::Boolean(true).to_string(), "true");
+    }
+
+    #[test]
+    fn absolute_paths_in_predicates() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_count(&doc, r#"//div[count(/html/body/div) = 3]"#, 3);
+        assert_eq_count(&doc, r#"//div[count(/html/body/div) = 2]"#, 0);
+        assert_eq_count(&doc, r#"//a[/html/@lang = "en"]"#, 4);
+
+        // Relative query, the absolute path must still start at the document root.
+        let group = evaluate(&doc, r#"//div[@class="group2"]"#)
+            .unwrap()
+            .and_then(|v| v.into_node())
+            .unwrap();
+
+        assert_eq!(
+            doc.evaluate_from(r#"a[count(/html/body/div) = 3]"#, &group)
+                .map(|v| v.count()),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn attribute_axis() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_count(&doc, r#"//div/attribute::class"#, 3);
+        assert_eq_count(&doc, r#"//div/attribute::*"#, 5);
+        assert_eq_count(&doc, r#"//div/attribute::node()"#, 5);
+        assert_eq_count(&doc, r#"//div/attribute::text()"#, 0);
+        assert_eq_eval_to_string(&doc, r#"//div/attribute::class"#, "test1");
+
+        let group = evaluate(&doc, r#"//div[@class="group1"]"#)
+            .unwrap()
+            .and_then(|v| v.into_node())
+            .unwrap();
+
+        let attr_values = |search: &str| {
+            doc.evaluate_from(search, &group)
+                .unwrap()
+                .map(|v| v.and_then(|v| v.convert_to_string()))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        assert_eq!(
+            attr_values(r#"attribute::class"#),
+            Ok(vec![String::from("group1")])
+        );
+        assert_eq!(
+            attr_values(r#"attribute::*"#),
+            Ok(vec![String::from("group1"), String::from("Watch Out!")])
+        );
+        assert_eq!(attr_values(r#"attribute::*"#), attr_values(r#"@*"#));
+        assert_eq!(attr_values(r#"attribute::node()"#), attr_values(r#"@*"#));
+
+        // Only elements have attributes.
+        assert_eq_count(&doc, r#"//div/text()/attribute::*"#, 0);
+    }
+
+    #[test]
+    fn relative_parent_steps() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let group = doc
+            .evaluate_unique(r#"//div[@class="group2"]"#)
+            .unwrap()
+            .unwrap();
+
+        let from = |search: &str, node: &Node| {
+            doc.evaluate_from(search, node)
+                .unwrap()
+                .map(|v| v.and_then(|v| v.convert_to_string()))
+                .collect::<Result<Vec<_>>>()
+                .unwrap()
+        };
+
+        // Back up to the starting node.
+        assert_eq!(from(r#".//img/../@class"#, &group), ["group2"]);
+        assert_eq!(
+            from(r#"string(.//img/..)"#, &group),
+            [group.get_string_value().unwrap()]
+        );
+
+        let img = doc
+            .evaluate_from(r#".//img"#, &group)
+            .unwrap()
+            .collect_nodes()
+            .unwrap();
+        assert_eq!(img.len(), 1);
+        let img = &img.nodes[0];
+
+        assert_eq!(from(r#"../@class"#, img), ["group2"]);
+        assert_eq!(from(r#"parent::div/@class"#, img), ["group2"]);
+
+        // And out of its subtree, the parent links are the document's.
+        assert_eq!(from(r#"name(../..)"#, img), ["body"]);
+        assert_eq!(from(r#"../../div[1]/@class"#, img), ["test1"]);
+        assert_eq!(
+            from(r#"../../div/@class"#, img),
+            ["test1", "group1", "group2"]
+        );
+        assert_eq!(from(r#"name(../../..)"#, img), ["html"]);
+        assert_eq!(from(r#"count(../../../..)"#, img), ["1"]);
+        assert_eq!(from(r#"count(../../../../..)"#, img), ["0"]);
+        assert_eq!(
+            from(r#"..//a/@class"#, &group),
+            ["clickable1", "clickable2", "clickable1"]
+        );
+    }
+
+    #[test]
+    fn self_axis() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let from = |search: &str, node: &Node| {
+            doc.evaluate_from(search, node)
+                .unwrap()
+                .nodes()
+                .collect::<Result<Vec<Node>>>()
+                .unwrap()
+        };
+
+        // Root
+        let root = doc.root.clone();
+        let found = from(r#"self::node()"#, &root);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].is_root());
+        assert!(from(r#"self::*"#, &root).is_empty());
+        assert!(from(r#"self::text()"#, &root).is_empty());
+
+        // Element
+        let div = evaluate(&doc, r#"//div"#)
+            .unwrap()
+            .and_then(|v| v.into_node())
+            .unwrap();
+        assert_eq!(from(r#"self::node()"#, &div), vec![div.clone()]);
+        assert_eq!(from(r#"self::*"#, &div), vec![div.clone()]);
+        assert_eq!(from(r#"self::div"#, &div), vec![div.clone()]);
+        assert!(from(r#"self::span"#, &div).is_empty());
+
+        // Attribute
+        let attr = evaluate(&doc, r#"//div/@class"#)
+            .unwrap()
+            .and_then(|v| v.into_node())
+            .unwrap();
+        let found = from(r#"self::node()"#, &attr);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].is_attribute());
+        assert!(from(r#"self::*"#, &attr).is_empty());
+        assert!(from(r#"self::div"#, &attr).is_empty());
+
+        // Text
+        let text = evaluate(&doc, r#"//div/text()"#)
+            .unwrap()
+            .and_then(|v| v.into_node())
+            .unwrap();
+        assert_eq!(from(r#"self::text()"#, &text).len(), 1);
+        assert_eq!(from(r#"self::node()"#, &text).len(), 1);
+        assert!(from(r#"self::*"#, &text).is_empty());
+
+        assert_eq_count(&doc, r#"//div/@class/self::node()"#, 3);
+        assert_eq_count(&doc, r#"//div/@class/self::*"#, 0);
+    }
+
+    #[test]
+    fn doctype() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let nodes = doc
+            .evaluate(r#"/child::node()"#)
+            .unwrap()
+            .nodes()
+            .collect::<Result<Vec<Node>>>()
+            .unwrap();
+
+        assert_eq!(nodes.len(), 2);
+        assert!(nodes[0].is_doctype());
+        assert_eq!(nodes[0].doctype_name(), Some(String::from("html")));
+        assert!(nodes[0].parent().unwrap().is_root());
+        assert!(nodes[0].children().is_empty());
+        assert_eq!(nodes[0].value(), Ok(Value::String(String::new())));
+
+        assert_eq!(nodes[1].doctype_name(), None);
+        assert_eq!(doc.root.doctype_name(), None);
+
+        // Only node() matches it.
+        assert_eq_count(&doc, r#"/*"#, 1);
+        assert_eq_count(&doc, r#"/text()"#, 0);
+        assert_eq_eval_to_string(&doc, r#"string(/node())"#, "");
+        assert_eq_count(&doc, r#"/html/preceding-sibling::node()"#, 1);
+    }
+
+    #[test]
+    fn all_nodes() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let nodes = doc
+            .evaluate(r#"//node()"#)
+            .unwrap()
+            .nodes()
+            .collect::<Result<Vec<Node>>>()
+            .unwrap();
+
+        // Every node except the root itself, which '//' starts from.
+        assert_eq!(nodes.len(), doc.node_count() - 1);
+        assert!(nodes
+            .iter()
+            .all(|node| !node.is_root() && !node.is_attribute()));
+        assert_eq!(nodes.iter().filter(|node| node.is_element()).count(), 20);
+        assert_eq!(nodes.iter().filter(|node| node.is_doctype()).count(), 1);
+
+        assert_eq_count(&doc, r#"/descendant::node()"#, nodes.len());
+        assert_eq_count(&doc, r#"/descendant-or-self::node()"#, nodes.len() + 1);
+        assert_eq_count(&doc, r#"//*"#, 20);
+        // No comments in the page, the doctype is the only other node.
+        assert_eq!(
+            nodes.len(),
+            doc.evaluate(r#"//* | //text()"#).unwrap().count() + 1
+        );
+
+        let doc = parse_document(&mut Cursor::new(r#"<p class="a">b<!-- c --></p>"#)).unwrap();
+
+        // html, head, body, p, "b" and the comment.
+        assert_eq_count(&doc, r#"//node()"#, 6);
+    }
+
+    #[test]
+    fn children_iter() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        for search in [r#"/"#, r#"/html"#, r#"//body"#, r#"//div[2]"#, r#"//title"#] {
+            let node = evaluate(&doc, search)
+                .unwrap()
+                .and_then(|v| v.into_node())
+                .unwrap();
+
+            assert_eq!(
+                node.children_iter().collect::<Vec<_>>(),
+                node.children(),
+                "{search}"
+            );
+        }
+
+        let body = evaluate(&doc, r#"//body"#)
+            .unwrap()
+            .and_then(|v| v.into_node())
+            .unwrap();
+
+        assert_eq!(
+            body.get_child(1).and_then(|n| n.tag_name()),
+            Some(String::from("div"))
+        );
+        assert_eq!(body.get_child(100), None);
+        assert_eq!(body.children_iter().filter(|n| n.is_element()).count(), 7);
+
+        let attr = evaluate(&doc, r#"//div/@class"#)
+            .unwrap()
+            .and_then(|v| v.into_node())
+            .unwrap();
+
+        assert_eq!(attr.children_iter().count(), 0);
+        assert_eq!(attr.get_child(0), None);
+    }
+
+    #[test]
+    fn parent_steps() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let first =
+            |search: &str| evaluate(&doc, search).map(|v| v.and_then(|v| v.into_node()).unwrap());
+
+        assert_eq!(
+            first(r#"//title/.."#).and_then(|n| n.tag_name()),
+            Some(String::from("head"))
+        );
+        assert_eq!(
+            first(r#"//title/../.."#).and_then(|n| n.tag_name()),
+            Some(String::from("html"))
+        );
+        assert!(first(r#"//title/../../.."#).unwrap().is_root());
+        assert!(first(r#"/html/.."#).unwrap().is_root());
+        assert_eq_count(&doc, r#"//title/../../../html"#, 1);
+
+        // Above the root is empty, not an error.
+        assert_is_none(&doc, r#"/.."#);
+        assert_is_none(&doc, r#"/../.."#);
+        assert_is_none(&doc, r#"//title/../../../.."#);
+        assert_is_none(&doc, r#"/html/../.."#);
+        assert_eq_count(&doc, r#"/../html"#, 0);
+
+        // An attribute's parent is the element holding it.
+        assert_eq_count(&doc, r#"//div/@class/.."#, 3);
+        assert!(first(r#"//div/@class/.."#).unwrap().is_element());
+        assert!(doc.root.parent().is_none());
+        assert!(first(r#"/node()"#).unwrap().parent().unwrap().is_root());
+    }
+
+    #[test]
+    fn tag_name() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq!(
+            first_node(&doc, r#"//div[1]"#).tag_name(),
+            Some(String::from("div"))
+        );
+        assert_eq!(
+            first_node(&doc, r#"//h1"#).tag_name(),
+            Some(String::from("h1"))
+        );
+        assert_eq!(first_node(&doc, r#"//div/@class"#).tag_name(), None);
+        assert_eq!(first_node(&doc, r#"//div/text()"#).tag_name(), None);
+        assert_eq!(doc.root.tag_name(), None);
+
+        // HTML tags are lowercased by the parser and XML ones keep their case.
+        let doc = parse_document(&mut Cursor::new(r#"<DIV><Span>a</Span></DIV>"#)).unwrap();
+        assert_eq!(
+            evaluate(&doc, r#"//span"#)
+                .unwrap()
+                .and_then(|v| v.into_node())
+                .unwrap()
+                .tag_name(),
+            Some(String::from("span"))
+        );
+
+        let doc = parse_xml_document(&mut Cursor::new(r#"<Root><Item/></Root>"#)).unwrap();
+        assert_eq!(
+            evaluate(&doc, r#"/Root/Item"#)
+                .unwrap()
+                .and_then(|v| v.into_node())
+                .unwrap()
+                .tag_name(),
+            Some(String::from("Item"))
+        );
+    }
+
+    #[test]
+    fn repeated_descendant_searches() {
+        let doc = parse_document(&mut Cursor::new(
+            r#"<div id="1"><div id="2"><div id="3"><a></a></div><a></a></div><a></a></div>"#,
+        ))
+        .unwrap();
+
+        assert_eq_count(&doc, r#"//div//a"#, 3);
+        assert_eq_count(&doc, r#"//div/descendant::a"#, 3);
+        assert_eq_count(&doc, r#"//div/descendant-or-self::div"#, 3);
+        // Predicates are relative to each div. Every div's first descendant anchor is the same one.
+        assert_eq_count(&doc, r#"//div/descendant::a[1]"#, 1);
+        assert_eq_count(&doc, r#"//div//a[1]"#, 3);
+
+        let divs = doc
+            .evaluate(r#"//div"#)
+            .unwrap()
+            .nodes()
+            .collect::<Result<Vec<Node>>>()
+            .unwrap();
+
+        let counter = Rc::new(Cell::new(0));
+        let mut step = Step::new(
+            AxisName::Descendant,
+            Box::new(CountingTest(counter.clone())),
+            Vec::new(),
+        );
+
+        let eval = Evaluation::new(&doc.root, &doc);
+        let found = step.evaluate(&eval, divs.into()).unwrap();
+
+        assert_eq!(found.len(), 3);
+        // Only the outer div's 5 descendants are tested, not the inner divs' again.
+        assert_eq!(counter.get(), 5);
+    }
+
+    #[test]
+    fn attribute_existence() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq!(
+            attribute_values(&doc, r#"//div[@class and @aria-label]"#, "class"),
+            ["group1", "group2"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"//div[@class and not(@aria-label)]"#, "class"),
+            ["test1"]
+        );
+        assert!(attribute_values(&doc, r#"//div[@aria-label and @missing]"#, "class").is_empty());
+        assert_eq!(
+            attribute_values(&doc, r#"//a[@missing or @class]"#, "class").len(),
+            3
+        );
+    }
+
+    #[test]
+    fn combined_predicates() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_count(
+            &doc,
+            r#"//div[contains(@class,'group') and @aria-label]"#,
+            2,
+        );
+        assert_eq_count(
+            &doc,
+            r#"//div[@aria-label and contains(@class,'group2')]"#,
+            1,
+        );
+        assert_eq_count(&doc, r#"//div[contains(@class,'test') and @aria-label]"#, 0);
+        assert_eq_count(&doc, r#"//div[contains(@class,'test') or @aria-label]"#, 3);
+        assert_eq_count(&doc, r#"//div[starts-with(@class,'group') and a]"#, 2);
+        assert_eq_count(&doc, r#"//div[starts-with(@class,'group') and img]"#, 1);
+        // A missing attribute is an empty string.
+        assert_eq_count(&doc, r#"//a[contains(@class, 'click') and not(@href)]"#, 3);
+        assert_eq_count(&doc, r#"//a[contains(@missing, '') and @class]"#, 3);
+        assert_eq_count(&doc, r#"//a[contains(@missing, 'x') or not(@class)]"#, 1);
+    }
+
+    #[test]
+    fn descendant_attributes() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq!(
+            evaluate_strings(&doc, r#"//@class"#),
+            [
+                "test1",
+                "test2",
+                "test3",
+                "group1",
+                "clickable1",
+                "clickable2",
+                "group2",
+                "clickable1"
+            ]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div//@class"#),
+            ["test1", "group1", "clickable1", "group2", "clickable1"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//@aria-label"#),
+            ["Watch Out!", "Come in!"]
+        );
+        assert_eq_count(&doc, r#"//@alt"#, 2);
+        assert_eq_count(&doc, r#"//@missing"#, 0);
+        assert_eq_count(&doc, r#"//@*"#, 20);
+    }
+
+    #[test]
+    fn existence_predicates() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_count(&doc, r#"//div[a]"#, 2);
+        assert_eq_count(&doc, r#"//div[count(a) > 0]"#, 2);
+        assert_eq_count(&doc, r#"//div[not(a)]"#, 1);
+        assert_eq_count(&doc, r#"//div[.//img]"#, 1);
+        assert_eq_count(&doc, r#"//div[a[1]]"#, 2);
+        assert_eq_count(&doc, r#"//div[a[2]]"#, 0);
+        assert_eq_count(&doc, r#"//body[div/a]"#, 1);
+
+        let body = first_node(&doc, r#"//body"#);
+
+        let eval = Evaluation::new(&doc.root, &doc);
+
+        let counter = Rc::new(Cell::new(0));
+        let mut step = Step::new(
+            AxisName::Child,
+            Box::new(CountingTest(counter.clone())),
+            Vec::new(),
+        );
+
+        assert_eq!(
+            step.evaluate(&eval, vec![body.clone()].into())
+                .unwrap()
+                .len(),
+            2
+        );
+        // Every child of the body: 7 elements and the 8 text nodes around them.
+        assert_eq!(counter.get(), 15);
+
+        counter.set(0);
+
+        assert!(step.exists(&eval, vec![body].into()).unwrap());
+        // Stops at the first anchor, the 8th child.
+        assert_eq!(counter.get(), 8);
+    }
+
+    #[test]
+    fn node_root() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let img = first_node(&doc, r#"//div[@class="group2"]/img"#);
+
+        let root = img.root();
+
+        assert!(root.is_root());
+        assert_eq!(root, doc.root);
+
+        let attr = first_node(&doc, r#"//div[@class="group2"]/img/@alt"#);
+
+        assert_eq!(attr.root(), doc.root);
+        assert_eq!(doc.root.root(), doc.root);
+
+        // Re-anchored document wide searches.
+        assert_eq!(
+            img.root().evaluate_from(r#"//img"#, &doc).unwrap().count(),
+            2
+        );
+    }
+
+    #[test]
+    fn nested_last() {
+        let doc = parse_document(&mut Cursor::new(
+            r#"<ul><li>a</li><li>b</li><li>c</li></ul><ul><li>d</li><li>e</li></ul>"#,
+        ))
+        .unwrap();
+
+        // last() is the size of each list, not of every li in the document.
+        assert_eq!(evaluate_strings(&doc, r#"//ul/li[last()]"#), ["c", "e"]);
+        assert_eq!(evaluate_strings(&doc, r#"//li[last()]"#), ["c", "e"]);
+        assert_eq!(
+            evaluate_strings(&doc, r#"//ul/li[position() != last()]"#),
+            ["a", "b", "d"]
+        );
+        assert_eq!(evaluate_strings(&doc, r#"//ul/li[last() - 1]"#), ["b", "d"]);
+        assert_eq!(
+            evaluate_strings(&doc, r#"//ul[li[last()] = "e"]/li[1]"#),
+            ["d"]
+        );
+
+        assert_eq_count(&doc, r#"//ul[count(li[position() < last()]) = 1]"#, 1);
+        assert_eq_count(&doc, r#"//ul[last()]"#, 1);
+    }
+
+    #[test]
+    fn fractional_positions() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_count(&doc, r#"//div[1]"#, 1);
+        assert_eq_count(&doc, r#"//div[2.0]"#, 1);
+        assert_eq_count(&doc, r#"//div[1.5]"#, 0);
+        assert_eq_count(&doc, r#"//div[1.9]"#, 0);
+        assert_eq_count(&doc, r#"//div[0]"#, 0);
+        assert_eq_count(&doc, r#"//div[0.5 + 0.5]"#, 1);
+        assert_eq_count(&doc, r#"//div[last() div 2]"#, 0);
+        assert_eq_count(&doc, r#"//div[0 div 0]"#, 0);
+    }
+
+    #[test]
+    fn chained_predicate_positions() {
+        let doc = parse_document(&mut Cursor::new(
+            r#"<body>
+                <p type="note">1</p>
+                <p type="warning">2</p>
+                <p>3</p>
+                <p type="warning">4</p>
+                <p type="warning">5</p>
+                <p type="note">6</p>
+                <p type="warning">7</p>
+            </body>"#,
+        ))
+        .unwrap();
+
+        // Positions in the second predicate are among the warnings only.
+        assert_eq_eval_to_string(&doc, r#"//p[@type="warning"][1]"#, "2");
+        assert_eq_eval_to_string(&doc, r#"//p[@type="warning"][3]"#, "5");
+        assert_eq_eval_to_string(&doc, r#"//p[@type="warning"][last()]"#, "7");
+        assert_eq_count(&doc, r#"//p[@type="warning"][5]"#, 0);
+        assert_eq_count(&doc, r#"//p[@type="warning"][position() > 2]"#, 2);
+
+        // The other way around the warnings are within the first three paragraphs.
+        assert_eq_count(&doc, r#"//p[position() <= 3][@type="warning"]"#, 1);
+        assert_eq_eval_to_string(&doc, r#"//p[position() > 3][@type="warning"][2]"#, "5");
+
+        // Each predicate renumbers the survivors of the one before it.
+        assert_eq_eval_to_string(&doc, r#"//p[@type][position() > 1][2]"#, "4");
+        assert_eq_eval_to_string(&doc, r#"(//p)[@type="warning"][2]"#, "4");
+    }
+
+    #[test]
+    fn size_only_predicates() {
+        use crate::functions::{Args, Function};
+
+        // last(), counting how often it's called.
+        #[derive(Debug)]
+        struct CountedLast(Rc<Cell<usize>>);
+
+        impl Function for CountedLast {
+            fn exec<'a>(&self, eval: &Evaluation, _: Args<'a>) -> Result<Value> {
+                self.0.set(self.0.get() + 1);
+                Ok(Value::Number(eval.size as f64))
+            }
+
+            fn only_uses_context_size(&self) -> bool {
+                true
+            }
+        }
+
+        let mut doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+        let calls = Rc::new(Cell::new(0));
+        doc.register_function("counted-last", CountedLast(calls.clone()));
+
+        // The same nodes as last(), with one call for the three body divs rather than one each.
+        assert_eq_eval_to_string(&doc, r#"//div[counted-last()]/@class"#, "group2");
+        assert_eq!(calls.get(), 1);
+        assert_eq_eval_to_string(&doc, r#"//div[last()]/@class"#, "group2");
+        assert_eq_eval_to_string(&doc, r#"//div[last() - 1]/@class"#, "group1");
+        assert_eq_eval_to_string(&doc, r#"//div[counted-last() - 1]/@class"#, "group1");
+        assert_eq_eval_to_string(&doc, r#"(//a)[last()]/@class"#, "clickable1");
+        assert_eq_count(&doc, r#"//body/*[last() * 2]"#, 0);
+        assert_eq_count(&doc, r#"//body/*[last() div 2]"#, 0);
+        assert_eq_count(&doc, r#"//body/*[last() - 6]"#, 1);
+        assert_eq_count(&doc, r#"//body/*["text"]"#, 7);
+        assert_eq_count(&doc, r#"//body/*[""]"#, 0);
+
+        // Depends on each node so it's still called for every one.
+        calls.set(0);
+        assert_eq_count(&doc, r#"//div[counted-last() = position()]"#, 1);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn position_comparisons() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        // A comparison is a boolean, true for every matching node rather than a position.
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div[position() != 1]/@class"#),
+            ["group1", "group2"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div[1 != position()]/@class"#),
+            ["group1", "group2"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div[position() != last()]/@class"#),
+            ["test1", "group1"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div[position() = 2]/@class"#),
+            ["group1"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div[not(position() = 2)]/@class"#),
+            ["test1", "group2"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div[position() != 99]/@class"#),
+            ["test1", "group1", "group2"]
+        );
+
+        // Unlike a number, which is compared to the position.
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div[position()]/@class"#),
+            ["test1", "group1", "group2"]
+        );
+        assert_eq!(evaluate_strings(&doc, r#"//div[2]/@class"#), ["group1"]);
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div[boolean(2)]/@class"#),
+            ["test1", "group1", "group2"]
+        );
+    }
+
+    #[test]
+    fn html_output() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let img = r#"<img src="" alt="unable to display">"#;
+
+        assert_eq!(
+            doc.evaluate(r#"//img"#).unwrap().to_html(),
+            Ok(format!("{img}{img}"))
+        );
+
+        let nodes = doc
+            .evaluate(r#"//h1 | //br"#)
+            .unwrap()
+            .collect_nodes()
+            .unwrap();
+
+        assert_eq!(nodes.to_html(), r#"<h1>The Group is here!</h1><br>"#);
+        assert_eq!(Nodeset::new().to_html(), "");
+
+        assert!(doc.evaluate(r#"count(//img)"#).unwrap().to_html().is_err());
+    }
+
+    #[test]
+    fn ancestors() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let anchor = first_node(&doc, r#"//div/a[@class="clickable1"]"#);
+
+        let ancestors = anchor.ancestors().collect::<Vec<_>>();
+
+        assert_eq!(ancestors.len(), 4);
+        assert_eq!(
+            Some(Ok(Value::Node(ancestors[0].clone()))),
+            doc.evaluate(r#"//div[@class="group1"]"#).unwrap().next()
+        );
+        assert_eq!(
+            ancestors
+                .iter()
+                .map(|node| node.tag_name())
+                .collect::<Vec<_>>(),
+            [
+                Some(String::from("div")),
+                Some(String::from("body")),
+                Some(String::from("html")),
+                None
+            ]
+        );
+        assert!(ancestors[3].is_root());
+
+        let class = anchor
+            .evaluate_from(r#"@class"#, &doc)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .into_node()
+            .unwrap();
+
+        // An attribute's first ancestor is its element.
+        assert_eq!(class.ancestors().next(), Some(anchor));
+        assert_eq!(doc.root.ancestors().count(), 0);
+    }
+
+    #[test]
+    fn index_in_parent() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq!(
+            first_node(&doc, r#"/html/body/div[3]"#).index_in_parent(),
+            Some(13)
+        );
+        assert_eq!(
+            first_node(&doc, r#"/html/body/div[1]"#).index_in_parent(),
+            Some(1)
+        );
+        assert_eq!(first_node(&doc, r#"//title"#).index_in_parent(), Some(7));
+        assert_eq!(first_node(&doc, r#"/html"#).index_in_parent(), Some(1));
+        assert_eq!(
+            first_node(&doc, r#"//h1/text()"#).index_in_parent(),
+            Some(0)
+        );
+        assert_eq!(first_node(&doc, r#"//div/@class"#).index_in_parent(), None);
+        assert_eq!(doc.root.index_in_parent(), None);
+
+        let div = first_node(&doc, r#"/html/body/div[3]"#);
+        let body = div.parent().unwrap();
+        assert_eq!(body.children()[div.index_in_parent().unwrap()], div);
+    }
+
+    #[test]
+    fn sibling_elements() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let test1 = first_node(&doc, r#"//div[@class="test1"]"#);
+        let test2 = first_node(&doc, r#"//span[@class="test2"]"#);
+
+        // The whitespace text between them is skipped.
+        assert_eq!(test1.next_sibling_element(), Some(test2.clone()));
+        assert_eq!(test1.previous_sibling_element(), None);
+        assert_eq!(test2.previous_sibling_element(), Some(test1));
+
+        assert_eq!(
+            first_node(&doc, r#"//div[@class="group2"]"#).previous_sibling_element(),
+            Some(first_node(&doc, r#"//a[@class="clickable2"]"#))
+        );
+        assert_eq!(
+            first_node(&doc, r#"//div[@class="group2"]"#).next_sibling_element(),
+            None
+        );
+        assert_eq!(
+            first_node(&doc, r#"//div/@class"#).next_sibling_element(),
+            None
+        );
+        assert_eq!(doc.root.next_sibling_element(), None);
+    }
+
+    #[test]
+    fn attribute_nodes() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let group = doc
+            .evaluate_unique(r#"//div[@class="group1"]"#)
+            .unwrap()
+            .unwrap();
+
+        let attrs = group.attribute_nodes();
+
+        assert_eq!(attrs.len(), 2);
+        assert!(attrs.iter().all(|a| a.is_attribute()));
+        assert_eq!(
+            attrs
+                .iter()
+                .map(|a| {
+                    let attr = a.attribute().unwrap();
+                    (attr.name_string(), attr.value().to_string())
+                })
+                .collect::<Vec<_>>(),
+            [
+                (String::from("class"), String::from("group1")),
+                (String::from("aria-label"), String::from("Watch Out!")),
+            ]
+        );
+
+        // The same nodes as '@*', usable in further queries.
+        assert_eq!(
+            attrs,
+            doc.evaluate(r#"//div[@class="group1"]/@*"#)
+                .unwrap()
+                .collect_nodes()
+                .unwrap()
+                .nodes
+        );
+        assert_eq!(attrs[0].parent(), Some(group));
+        assert_eq!(
+            attrs[1].evaluate_from(r#"string(.)"#, &doc).unwrap().next(),
+            Some(Ok(Value::String("Watch Out!".into())))
+        );
+
+        assert!(attrs[0].attribute_nodes().is_empty());
+        assert!(doc.root.attribute_nodes().is_empty());
+    }
+
+    #[test]
+    fn try_children() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let group = first_node(&doc, r#"//div[@class="group1"]"#);
+        let class = first_node(&doc, r#"//div/@class"#);
+
+        assert_eq!(group.try_children().map(|v| v.len()), Some(7));
+        assert_eq!(group.try_children(), Some(group.children()));
+        assert_eq!(doc.root.try_children().map(|v| v.len()), Some(2));
+        assert_eq!(class.try_children(), Some(Vec::new()));
+        assert!(class.children().is_empty());
+
+        drop(doc);
+
+        assert_eq!(group.try_children(), None);
+    }
+
+    #[test]
+    fn owned_subtree() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let group = doc
+            .evaluate_unique(r#"//div[@class="group1"]"#)
+            .unwrap()
+            .unwrap();
+        let text = group.get_string_value().unwrap();
+
+        let owned = group.to_owned_subtree();
+
+        drop(doc);
+        assert_eq!(group.try_children(), None);
+
+        assert_eq!(owned.kind, NodeKind::Element);
+        assert_eq!(owned.name.as_deref(), Some("div"));
+        assert_eq!(owned.attribute("class"), Some("group1"));
+        assert_eq!(owned.attribute("aria-label"), Some("Watch Out!"));
+        assert_eq!(owned.attribute("missing"), None);
+        assert_eq!(owned.text(), text);
+        assert_eq!(owned.children.len(), 7);
+
+        let elements = owned
+            .children
+            .iter()
+            .filter(|c| c.kind == NodeKind::Element)
+            .collect::<Vec<_>>();
+
+        assert_eq!(elements.len(), 3);
+        assert_eq!(elements[0].text(), "The Group is here!");
+        assert_eq!(elements[2].name.as_deref(), Some("a"));
+        assert_eq!(elements[2].attribute("class"), Some("clickable1"));
+        assert_eq!(
+            elements[2].children[0].value.as_deref(),
+            Some("Don't click!")
+        );
+
+        // Deep enough to overflow the stack if it was copied recursively.
+        let depth = 20_000;
+        let doc = parse_document(&mut Cursor::new("<template>".repeat(depth) + "deep")).unwrap();
+
+        let owned = first_node(&doc, r#"/html/head/template"#).to_owned_subtree();
+        assert_eq!(owned.text(), "deep");
+
+        let mut deepest = &owned;
+        let mut templates = 1;
+
+        while let Some(child) = deepest.children.last() {
+            if child.kind == NodeKind::Element {
+                templates += 1;
+            }
+
+            deepest = child;
+        }
+
+        assert_eq!(templates, depth);
+        assert_eq!(deepest.value.as_deref(), Some("deep"));
+    }
+
+    #[test]
+    fn source_spans() {
+        let doc = parse_document_with_spans(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let span = |search: &str| {
+            let node = first_node(&doc, search);
+            node.source_span(&doc).map(|span| &WEBPAGE[span])
+        };
+
+        assert_eq!(span(r#"//h1"#), Some("<h1>The Group is here!</h1>"));
+        assert_eq!(
+            span(r#"//span[2]"#),
+            Some(r#"<span class="test3">Testing 3</span>"#)
+        );
+        assert_eq!(span(r#"//br"#), Some("<br/>"));
+
+        let group = span(r#"//div[@class="group1"]"#).unwrap();
+        assert!(group.starts_with(r#"<div class="group1" aria-label="Watch Out!">"#));
+        assert!(group.ends_with("</a>\n\t\t\t\t</div>"));
+        assert!(!group.contains("clickable2"));
+
+        // Elements sharing a line, with a comment and text containing '<' and '>'.
+        let source = r#"<ul><li id="a">1 < 2</li><!-- > --><li title="<x>">3 > 2</li></ul>"#;
+        let line = parse_document_with_spans(&mut Cursor::new(source)).unwrap();
+        let line_span = |search: &str| {
+            let node = first_node(&line, search);
+            node.source_span(&line).map(|span| &source[span])
+        };
+
+        assert_eq!(line_span(r#"//li[1]"#), Some(r#"<li id="a">1 < 2</li>"#));
+        assert_eq!(
+            line_span(r#"//li[2]"#),
+            Some(r#"<li title="<x>">3 > 2</li>"#)
+        );
+        assert_eq!(line_span(r#"//comment()"#), Some("<!-- > -->"));
+        assert_eq!(line_span(r#"//ul"#), Some(source));
+
+        // Closed without their own end tags.
+        let source = "<div>\n<p>one\n<p>two\n</div>\n<span>after</span>";
+        let nested = parse_document_with_spans(&mut Cursor::new(source)).unwrap();
+        let nested_span = |search: &str| {
+            let node = first_node(&nested, search);
+            node.source_span(&nested).map(|span| &source[span])
+        };
+
+        assert_eq!(
+            nested_span(r#"//div"#),
+            Some("<div>\n<p>one\n<p>two\n</div>")
+        );
+        assert_eq!(nested_span(r#"//p[1]"#), Some("<p>one\n"));
+        assert_eq!(nested_span(r#"//p[2]"#), Some("<p>two\n"));
+        assert_eq!(nested_span(r#"//span"#), Some("<span>after</span>"));
+
+        // Only elements and comments.
+        assert_eq!(span(r#"//div/@class"#), None);
+        assert_eq!(span(r#"//h1/text()"#), None);
+
+        // Not tracked unless parsed with spans.
+        let untracked = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+        assert_eq!(
+            first_node(&untracked, r#"//h1"#).source_span(&untracked),
+            None
+        );
+    }
+
+    #[test]
+    fn node_kinds() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let kinds = |doc: &Document, search: &str| {
+            doc.evaluate(search)
+                .unwrap()
+                .map(|v| v.unwrap().into_node().unwrap().kind())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(doc.root.kind(), NodeKind::Root);
+        assert_eq!(
+            kinds(&doc, r#"/node()"#),
+            [NodeKind::DocType, NodeKind::Element]
+        );
+        assert_eq!(kinds(&doc, r#"//title/text()"#), [NodeKind::Text]);
+        assert_eq!(
+            kinds(&doc, r#"//div[@class="group1"]/@*"#),
+            [NodeKind::Attribute, NodeKind::Attribute]
+        );
+
+        let comment = parse_document(&mut Cursor::new("<body><!-- c --></body>")).unwrap();
+        assert_eq!(kinds(&comment, r#"//comment()"#), [NodeKind::Comment]);
+
+        let xml = parse_xml_document(&mut Cursor::new(r#"<?pi x?><a/>"#)).unwrap();
+        assert_eq!(
+            kinds(&xml, r#"//processing-instruction()"#),
+            [NodeKind::ProcessingInstruction]
+        );
+    }
+
+    #[test]
+    fn any_node_test_axes() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let kinds = |search: &str| {
+            doc.evaluate(search)
+                .unwrap()
+                .map(|v| v.unwrap().into_node().unwrap().kind())
+                .collect::<Vec<_>>()
+        };
+
+        // Attributes aren't children.
+        let children = kinds(r#"//div/node()"#);
+        assert_eq!(children.len(), 13);
+        assert!(!children.contains(&NodeKind::Attribute));
+        assert_eq!(kinds(r#"//div/child::node()"#), children);
+
+        let attributes = kinds(r#"//div/@node()"#);
+        assert_eq!(attributes.len(), 5);
+        assert!(attributes.iter().all(|kind| *kind == NodeKind::Attribute));
+        assert_eq!(kinds(r#"//div/attribute::node()"#), attributes);
+        assert_eq_count(&doc, r#"//div/@*"#, 5);
+
+        assert!(!kinds(r#"//div//node()"#).contains(&NodeKind::Attribute));
+    }
+
+    #[test]
+    fn dom_handles() {
+        use markup5ever_rcdom::NodeData;
+
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let img = first_node(&doc, r#"//img"#);
+
+        let handle = img.as_handle().unwrap();
+
+        match &handle.data {
+            NodeData::Element { name, attrs, .. } => {
+                assert_eq!(&*name.local, "img");
+                assert_eq!(attrs.borrow().len(), 2);
+            }
+            data => panic!("expected an element, found {data:?}"),
+        }
+
+        assert!(matches!(doc.dom_root().data, NodeData::Document));
+        assert_eq!(doc.dom_root().children.borrow().len(), 2);
+        assert_eq!(Node::from(doc.dom_root()), doc.root);
+
+        let alt = img
+            .evaluate_from(r#"@alt"#, &doc)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .into_node()
+            .unwrap();
+
+        assert!(alt.as_handle().is_none());
+    }
+
+    #[test]
+    fn trimmed_documents() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+        let trimmed = parse_document_trimmed(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_count(&doc, r#"//body//text()"#, 24);
+        assert_eq_count(&trimmed, r#"//body//text()"#, 7);
+        assert_eq_count(&trimmed, r#"/html/body/node()"#, 7);
+        assert_eq_count(&trimmed, r#"//*"#, 20);
+        assert_eq_eval_to_string(
+            &trimmed,
+            r#"//div[@class="group1"]/h1"#,
+            "The Group is here!",
+        );
+
+        let pre = parse_document_trimmed(&mut Cursor::new(
+            "<body> <pre>  <b>a</b>  </pre> <p> </p></body>",
+        ))
+        .unwrap();
+
+        assert_eq_count(&pre, r#"//pre/text()"#, 2);
+        assert_eq_count(&pre, r#"//p/text()"#, 0);
+        assert_eq_count(&pre, r#"/html/body/text()"#, 0);
+    }
+
+    #[test]
+    fn reader_errors() {
+        // Reads part of a document, then fails.
+        struct FailingReader(bool);
+
+        impl std::io::Read for FailingReader {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if std::mem::replace(&mut self.0, true) {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::ConnectionReset,
+                        "peer went away",
+                    ));
+                }
+
+                let data = b"<body><p>partial";
+                buf[..data.len()].copy_from_slice(data);
+                Ok(data.len())
+            }
+        }
+
+        let error = Document::from_reader(&mut FailingReader(false)).err();
+
+        assert_eq!(
+            error,
+            Some(Error::Io {
+                kind: std::io::ErrorKind::ConnectionReset,
+                message: String::from("peer went away"),
+            })
+        );
+        assert_eq!(error.unwrap().to_string(), "IO Error: peer went away");
+
+        assert!(matches!(
+            parse_xml_document(&mut FailingReader(false)),
+            Err(Error::Io { .. })
+        ));
+
+        let doc = Document::from_reader(&mut Cursor::new(WEBPAGE)).unwrap();
+        assert_eq_count(&doc, r#"//div"#, 3);
+    }
+
+    #[test]
+    fn all_text() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let text = doc.all_text();
+
+        let testing = text.find("Testing 1").unwrap();
+        let open = text.find("Open Here!").unwrap();
+        assert!(testing < open);
+        assert!(text.contains("Document"));
+
+        assert_eq_eval_to_string(&doc, r#"string(/)"#, &text);
+
+        let empty = parse_xml_document(&mut Cursor::new(r#"<a><b/></a>"#)).unwrap();
+        assert_eq!(empty.all_text(), "");
+    }
+
+    #[test]
+    fn descendant_text() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+        let trimmed = parse_document_trimmed(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq!(
+            evaluate_strings(&trimmed, r#"//div[@class='group1']//text()"#),
+            ["The Group is here!", "Don't click!"]
+        );
+        assert_eq!(
+            evaluate_strings(
+                &doc,
+                r#"//div[@class='group1']//text()[normalize-space(.)]"#
+            ),
+            ["The Group is here!", "Don't click!"]
+        );
+        assert_eq!(
+            evaluate_strings(&trimmed, r#"//div[@class='group1']/descendant::text()"#),
+            ["The Group is here!", "Don't click!"]
+        );
+        // Only the whitespace between its children is directly inside it.
+        assert!(evaluate_strings(&trimmed, r#"//div[@class='group1']/text()"#).is_empty());
+        assert_eq_count(&doc, r#"//div[@class='group1']//text()"#, 6);
+
+        let group = first_node(&trimmed, r#"//div[@class='group2']"#);
+
+        assert_eq!(
+            group
+                .evaluate_from(r#".//text()"#, &trimmed)
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn element_node_test() {
+        use crate::nodetest::Element;
+
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let any = Element::new(NameTest {
+            prefix: None,
+            local_part: String::from("*"),
+        });
+
+        let mut found = Nodeset::new();
+        any.test(&Evaluation::new(&doc.root, &doc), &mut found);
+        assert!(found.is_empty());
+
+        let title = first_node(&doc, r#"//title"#);
+
+        any.test(&Evaluation::new(&title, &doc), &mut found);
+        assert_eq!(found.len(), 1);
+
+        // The title's DOM node is dropped alongside its document.
+        drop(doc);
+
+        let other = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+        let mut found = Nodeset::new();
+        any.test(&Evaluation::new(&title, &other), &mut found);
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn template_contents() {
+        let doc = parse_document(&mut Cursor::new(
+            r#"<body><template id="row"><div class="inner">a</div><span>b</span></template></body>"#,
+        ))
+        .unwrap();
+
+        assert_eq_count(&doc, r#"//template/div"#, 1);
+        assert_eq_count(&doc, r#"//template//div"#, 1);
+        assert_eq_count(&doc, r#"//template/*"#, 2);
+        assert_eq_count(&doc, r#"//div[@class="inner"]"#, 1);
+        assert_eq_count(&doc, r#"//template/div/following-sibling::span"#, 1);
+        assert_eq_eval_to_string(&doc, r#"string(//template)"#, "ab");
+        assert_eq_eval_to_string(&doc, r#"//template/div"#, "a");
+
+        // Going up from inside leads back to the template, not to the fragment holding its contents.
+        assert_eq_count(&doc, r#"//template/div/.."#, 1);
+        assert_eq_eval_to_string(&doc, r#"name(//div/..)"#, "template");
+        assert_eq_eval_to_string(&doc, r#"//div/../@id"#, "row");
+        assert_eq_count(&doc, r#"//div/ancestor::*"#, 3);
+        assert_eq_count(&doc, r#"//div/ancestor::body"#, 1);
+        assert_eq_count(&doc, r#"//div[/html]"#, 1);
+        assert_eq_count(&doc, r#"//span/preceding::*"#, 2);
+        assert_eq_eval_to_string(&doc, r#"name((//div | //template)[1])"#, "template");
+    }
+
+    #[test]
+    fn deep_documents() {
+        // Deep enough to overflow the stack if the tree was walked recursively when parsing.
+        let depth = 20_000;
+
+        // Each template is parsed into the contents of the one before it.
+        let doc = parse_document(&mut Cursor::new("<template>".repeat(depth))).unwrap();
+
+        // html and head are above the templates.
+        assert_eq!(doc.max_depth(), depth + 2);
+
+        let mut deepest = doc.root.clone();
+
+        while let Some(child) = deepest.children_iter().last() {
+            deepest = child;
+        }
+
+        // Every template's contents were linked back to it on the way down.
+        assert_eq!(deepest.root(), doc.root);
+
+        // The text at the bottom is found without recursing through every template.
+        let doc = parse_document(&mut Cursor::new("<template>".repeat(depth) + "deep")).unwrap();
+
+        assert_eq!(doc.root.get_string_value(), Ok(String::from("deep")));
+        assert_eq_eval_to_string(&doc, r#"string(/html/head/template)"#, "deep");
+    }
+
+    #[test]
+    fn node_value_equality() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_eval(&doc, r#"//a[1] = //a[1]"#, true);
+        // Different images, both with an empty string-value.
+        assert_eq_eval(&doc, r#"//div/img = //a/img"#, true);
+        assert_eq_eval(&doc, r#"//div/img != //a/img"#, false);
+        assert_eq_eval(&doc, r#"//h1 = //title"#, false);
+        assert_eq_eval(&doc, r#"//h1 != //title"#, true);
+
+        let imgs = doc
+            .evaluate(r#"//img"#)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert!(imgs[0].equals(&imgs[1]));
+        // Identity is still what == compares.
+        assert_ne!(imgs[0], imgs[1]);
+
+        let (first, second) = (imgs[0].as_node().unwrap(), imgs[1].as_node().unwrap());
+
+        assert!(first.same_node(first));
+        assert!(!first.same_node(second));
+        assert!(doc.root.same_node(&doc.root));
+        assert!(!doc.root.same_node(first));
+
+        // Unions drop the same node, not nodes with equal values.
+        assert_eq_count(&doc, r#"//div/img | //a/img"#, 2);
+        assert_eq_count(&doc, r#"//img | //div/img"#, 2);
+    }
+
+    #[test]
+    fn existential_equality() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_count(&doc, r#"//div[@class = 'group1']"#, 1);
+        assert_eq_count(&doc, r#"//div[. = 'Testing 1']"#, 1);
+        assert_eq_count(&doc, r#"//div['Testing 1' = .]"#, 1);
+        assert_eq_count(&doc, r#"//div[a = 'Open Here!']"#, 1);
+        assert_eq_count(&doc, r#"//div[a = "Don't click!"]"#, 1);
+        assert_eq_count(&doc, r#"//div[a != 'Open Here!']"#, 1);
+        assert_eq_count(&doc, r#"//div[a = 'Maybe']"#, 0);
+
+        // Any of the anchors, not only the first.
+        assert_eq_eval(&doc, r#"//a = 'Open Here!'"#, true);
+        assert_eq_eval(&doc, r#"//a = 'Missing'"#, false);
+        assert_eq_eval(&doc, r#"//a != 'Maybe'"#, true);
+        assert_eq_eval(&doc, r#"//title != 'Document'"#, false);
+        assert_eq_eval(&doc, r#"//a/@class = //div/a/@class"#, true);
+        assert_eq_eval(&doc, r#"//span/@class = //div/@class"#, false);
+
+        // An empty node-set is never equal or unequal.
+        assert_eq_eval(&doc, r#"//video = ''"#, false);
+        assert_eq_eval(&doc, r#"//video != ''"#, false);
+
+        // Compared to a boolean, a node-set is converted with boolean() instead.
+        assert_eq_eval(&doc, r#"//a = true()"#, true);
+        assert_eq_eval(&doc, r#"//a = false()"#, false);
+        assert_eq_eval(&doc, r#"//a != true()"#, false);
+        assert_eq_eval(&doc, r#"false() != //a"#, true);
+        assert_eq_eval(&doc, r#"//video = false()"#, true);
+        assert_eq_eval(&doc, r#"//video = true()"#, false);
+        assert_eq_eval(&doc, r#"//video != false()"#, false);
+        assert_eq_eval(&doc, r#"true() != //video"#, true);
+        assert_eq_count(&doc, r#"//div[img = true()]"#, 1);
+        assert_eq_count(&doc, r#"//div[img = false()]"#, 2);
+    }
+
+    #[test]
+    fn predicates_on_repeated_nodes() {
+        // The last div follows both p, the div of span and b is the ancestor of both.
+        // The predicate checks that div once for each node it's found from, one after the other.
+        let doc = parse_document(&mut Cursor::new(
+            r#"<p>1</p><div><span>a</span></div><p>2</p><div><span>c</span><b>d</b></div>"#,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            evaluate_strings(&doc, r#"//p/following::div[not(span = 'c')]"#),
+            ["a"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//p/following::div[string(span) != 'c']"#),
+            ["a"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//p/following::div[not(string(*) = 'c')]"#),
+            ["a"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//p/following::div[span = 'c']"#),
+            ["cd"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div/*/ancestor::div[not(* = 'd')]"#),
+            ["a"]
+        );
+
+        // Filter expressions too.
+        assert_eq!(
+            evaluate_strings(&doc, r#"//p/following::div[not((*)[1] = 'c')]"#),
+            ["a"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//div/*/ancestor::div[not((span | b)[2] = 'd')]"#),
+            ["a"]
+        );
+    }
+
+    #[test]
+    fn take_nodes() {
+        let page = format!(
+            "<body>{}</body>",
+            (0..100)
+                .map(|i| format!(r#"<div id="{i}"><a>{i}</a></div>"#))
+                .collect::<String>()
+        );
+
+        let doc = parse_document(&mut Cursor::new(page)).unwrap();
+
+        let ids = |nodes: Vec<Node>| {
+            nodes
+                .into_iter()
+                .map(|node| node.get_string_value().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ids(doc.evaluate(r#"//div"#).unwrap().take_nodes(3).unwrap()),
+            ["0", "1", "2"]
+        );
+        assert_eq!(
+            ids(doc.evaluate(r#"//div/a"#).unwrap().take_nodes(2).unwrap()),
+            ["0", "1"]
+        );
+        assert_eq!(
+            ids(doc
+                .evaluate(r#"//div[@id = "50"]"#)
+                .unwrap()
+                .take_nodes(2)
+                .unwrap()),
+            ["50"]
+        );
+        assert_eq!(
+            doc.evaluate(r#"//div"#)
+                .unwrap()
+                .take_nodes(1000)
+                .unwrap()
+                .len(),
+            100
+        );
+        assert!(doc
+            .evaluate(r#"//span"#)
+            .unwrap()
+            .take_nodes(1)
+            .unwrap()
+            .is_empty());
+        assert!(doc
+            .evaluate(r#"count(//div)"#)
+            .unwrap()
+            .take_nodes(1)
+            .is_err());
+
+        // Continues from where iteration left off.
+        let mut iter = doc.evaluate(r#"//div"#).unwrap();
+        iter.next();
+        assert_eq!(ids(iter.take_nodes(2).unwrap()), ["1", "2"]);
+
+        let body = first_node(&doc, r#"//body"#);
+
+        let counter = Rc::new(Cell::new(0));
+        let mut step = Step::new(
+            AxisName::Descendant,
+            Box::new(CountingTest(counter.clone())),
+            Vec::new(),
+        );
+
+        let eval = Evaluation::new(&doc.root, &doc);
+        let found = step.evaluate_limited(&eval, vec![body].into(), 1).unwrap();
+
+        assert_eq!(found.len(), 1);
+        // The first div, its anchor and nothing past it.
+        assert_eq!(counter.get(), 2);
+    }
+
+    #[test]
+    fn collect_nodes() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq!(doc.evaluate_all(r#"1+1"#), Ok(vec![Value::Number(2.0)]));
+        assert!(doc.evaluate(r#"1+1"#).unwrap().collect_nodes().is_err());
+
+        let values = doc.evaluate_all(r#"//div"#).unwrap();
+        assert_eq!(values.len(), 3);
+        assert!(values.iter().all(Value::is_node));
+        assert_eq!(
+            doc.evaluate(r#"//div"#)
+                .unwrap()
+                .collect_nodes()
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn collect_into() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let mut set = Nodeset::new();
+
+        doc.evaluate(r#"//div"#)
+            .unwrap()
+            .collect_into(&mut set)
+            .unwrap();
+        assert_eq!(set.len(), 3);
+
+        // Appended after the divs, not merged into document order.
+        doc.evaluate(r#"//span"#)
+            .unwrap()
+            .collect_into(&mut set)
+            .unwrap();
+        assert_eq!(set.len(), 5);
+        assert_eq!(
+            set.nodes
+                .iter()
+                .map(|n| n.tag_name().unwrap())
+                .collect::<Vec<_>>(),
+            ["div", "div", "div", "span", "span"]
+        );
+
+        // Nothing is added by a value which isn't a node.
+        assert_eq!(
+            doc.evaluate(r#"1 + 1"#).unwrap().collect_into(&mut set),
+            Err(Error::InvalidValue(ValueError::Nodeset))
+        );
+        assert_eq!(set.len(), 5);
+
+        set.nodes.clear();
+        doc.evaluate(r#"//h1"#)
+            .unwrap()
+            .collect_into(&mut set)
+            .unwrap();
+        assert_eq!(
+            set.nodes,
+            doc.evaluate(r#"//h1"#)
+                .unwrap()
+                .collect_nodes()
+                .unwrap()
+                .nodes
+        );
+    }
+
+    #[test]
+    fn unique_strings() {
+        let doc = parse_document(&mut Cursor::new(
+            r#"<ul>
+                <li>Apple</li>
+                <li>Pear</li>
+                <li>Apple</li>
+                <li class="Pear">Plum</li>
+                <li>Pear</li>
+            </ul>"#,
+        ))
+        .unwrap();
+
+        let unique = |search: &str| doc.evaluate(search).unwrap().unique_strings();
+
+        assert_eq!(
+            unique(r#"//li"#),
+            Ok(vec![
+                String::from("Apple"),
+                String::from("Pear"),
+                String::from("Plum")
+            ])
+        );
+        // Nodes of any kind are compared by string-value.
+        assert_eq!(
+            unique(r#"//li/text() | //li/@class"#),
+            Ok(vec![
+                String::from("Apple"),
+                String::from("Pear"),
+                String::from("Plum")
+            ])
+        );
+        assert_eq!(unique(r#"//li[last()]"#), Ok(vec![String::from("Pear")]));
+        assert_eq!(unique(r#"count(//li)"#), Ok(vec![String::from("5")]));
+        assert_eq!(unique(r#"//missing"#), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn unmatched_queries() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let values = |search: &str| doc.evaluate_all(search);
+
+        // Valid queries which find nothing are empty, not errors.
+        for search in [
+            r#"//nonexistent"#,
+            r#"//div[@missing]"#,
+            r#"//div[99]"#,
+            r#"/nonexistent/div"#,
+            r#"//div[@class="missing"]/a"#,
+            r#"(//nonexistent)[1]"#,
+            r#"//div/@missing"#,
+            r#"//nonexistent | //missing"#,
+        ] {
+            assert_eq!(values(search), Ok(Vec::new()), "{search}");
+        }
+
+        // An argument which finds nothing is an empty node-set.
+        assert_eq_eval_to_string(&doc, r#"string(//nonexistent)"#, "");
+        assert_eq_eval(&doc, r#"string-length(//nonexistent)"#, 0.0);
+        assert_eq_eval_to_string(&doc, r#"normalize-space(//nonexistent)"#, "");
+        assert_eq_eval_to_string(&doc, r#"substring-before(//nonexistent, "a")"#, "");
+        assert_eq_eval_to_string(&doc, r#"substring-after("abc", //nonexistent)"#, "");
+        assert_eq_eval_to_string(&doc, r#"substring(//nonexistent, 1)"#, "");
+        assert_eq_eval(&doc, r#"count(//nonexistent)"#, 0.0);
+        assert_eq_count(&doc, r#"//div[string(@missing) = '']"#, 3);
+    }
+
+    #[test]
+    fn document_exists() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq!(doc.exists(r#"//div"#), Ok(true));
+        assert_eq!(doc.exists(r#"//video"#), Ok(false));
+        assert_eq!(doc.exists(r#"//div[@class="group2"]/img"#), Ok(true));
+        assert_eq!(doc.exists(r#"count(//div) = 3"#), Ok(true));
+        assert_eq!(doc.exists(r#"count(//video) = 1"#), Ok(false));
+        assert_eq!(doc.exists(r#"string(//video)"#), Ok(false));
+        assert!(doc.exists(r#"//div["#).is_err());
+    }
+
+    #[test]
+    fn evaluate_grouped() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let sizes = |groups: Vec<(String, Vec<Node>)>| {
+            groups
+                .into_iter()
+                .map(|(key, nodes)| (key, nodes.len()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sizes(doc.evaluate_grouped(r#"//a"#, r#"@class"#).unwrap()),
+            [
+                (String::new(), 1),
+                (String::from("clickable1"), 2),
+                (String::from("clickable2"), 1)
+            ]
+        );
+        assert_eq!(
+            sizes(
+                doc.evaluate_grouped(r#"//*[@class]"#, r#"local-name(.)"#)
+                    .unwrap()
+            ),
+            [
+                (String::from("div"), 3),
+                (String::from("span"), 2),
+                (String::from("a"), 3)
+            ]
+        );
+
+        let table = parse_document(&mut Cursor::new(
+            r#"<table><tr><td>a</td><td>1</td></tr><tr><td>b</td><td>2</td></tr><tr><td>a</td><td>3</td></tr></table>"#,
+        ))
+        .unwrap();
+
+        let groups = table.evaluate_grouped(r#"//tr"#, r#"td[1]"#).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].0, "a");
+        assert_eq!(
+            groups[0]
+                .1
+                .iter()
+                .map(|tr| tr.get_string_value().unwrap())
+                .collect::<Vec<_>>(),
+            ["a1", "a3"]
+        );
+        assert_eq!(groups[1].0, "b");
+
+        assert!(doc.evaluate_grouped(r#"//a"#, r#"@["#).is_err());
+        assert!(doc.evaluate_grouped(r#"count(//a)"#, r#"."#).is_err());
+    }
+
+    #[test]
+    fn evaluate_with_context() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+        let div = doc
+            .evaluate_unique(r#"//div[@class="group1"]"#)
+            .unwrap()
+            .unwrap();
+
+        let first = |search: &str| {
+            doc.evaluate_with_context(search, &div, 2, 3)
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap()
+        };
+
+        assert_eq!(first(r#"position()"#), Value::Number(2.0));
+        assert_eq!(first(r#"last()"#), Value::Number(3.0));
+        assert_eq!(first(r#"position() = last()"#), Value::Boolean(false));
+        assert_eq!(first(r#"string(@class)"#), Value::String("group1".into()));
+
+        // Steps from the node start their own context.
+        assert_eq!(
+            doc.evaluate_with_context(r#"*[position() = 2]"#, &div, 2, 3)
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn custom_functions() {
+        use crate::functions::{Args, Function};
+
+        // position() * 2
+        #[derive(Debug)]
+        struct DoublePosition;
+
+        impl Function for DoublePosition {
+            fn exec<'a>(&self, eval: &Evaluation, _: Args<'a>) -> Result<Value> {
+                Ok(Value::Number(eval.position as f64 * 2.0))
+            }
+        }
+
+        // last() plus the argument
+        #[derive(Debug)]
+        struct FromLast;
+
+        impl Function for FromLast {
+            fn exec<'a>(&self, eval: &Evaluation, mut args: Args<'a>) -> Result<Value> {
+                let offset = args.get_required_value(0, eval)?.number()?;
+
+                Ok(Value::Number(eval.size as f64 + offset))
+            }
+        }
+
+        // The context node's tag name.
+        #[derive(Debug)]
+        struct Tag;
+
+        impl Function for Tag {
+            fn exec<'a>(&self, eval: &Evaluation, _: Args<'a>) -> Result<Value> {
+                Ok(Value::String(eval.node.tag_name().unwrap_or_default()))
+            }
+        }
+
+        let mut doc = parse_document(&mut Cursor::new(
+            r#"<ul><li>a</li><li>b</li><li>c</li></ul><ul><li>d</li><li>e</li></ul>"#,
+        ))
+        .unwrap();
+
+        doc.register_function("double-position", DoublePosition);
+        doc.register_function("from-last", FromLast);
+        doc.register_function("tag", Tag);
+        doc.register_function("count", Tag);
+
+        assert_eq!(
+            evaluate_strings(&doc, r#"//li[double-position() = 4]"#),
+            ["b", "e"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//li[position() = from-last(-1)]"#),
+            ["b", "d"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//ul/*[tag() = "li"][1]"#),
+            ["a", "d"]
+        );
+        assert_eq!(evaluate_strings(&doc, r#"tag()"#), [""]);
+        // Built-in functions can't be replaced.
+        assert_eq!(evaluate_strings(&doc, r#"count(//li)"#), ["5"]);
+
+        // Clones share the registered functions.
+        let copy = doc.clone();
+        assert_eq_count(&copy, r#"//li[double-position() = 2]"#, 2);
+    }
+
+    #[test]
+    fn function_rooted_paths() {
+        use crate::functions::{Args, Function};
+
+        // The divs with an aria-label.
+        #[derive(Debug)]
+        struct Groups;
+
+        impl Function for Groups {
+            fn exec<'a>(&self, eval: &Evaluation, _: Args<'a>) -> Result<Value> {
+                Ok(Value::Nodeset(
+                    eval.document
+                        .evaluate(r#"//div[@aria-label]"#)?
+                        .collect_nodes()?,
+                ))
+            }
+        }
+
+        let mut doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+        doc.register_function("groups", Groups);
+
+        let divs = doc.evaluate(r#"//div"#).unwrap().collect_nodes().unwrap();
+        doc.set_variable("divs", divs);
+
+        assert_eq_eval_to_string(&doc, r#"string(//title)"#, "Document");
+        assert_eq_eval(&doc, r#"count(//div)"#, 3.0);
+
+        assert_eq_count(&doc, r#"groups()"#, 2);
+        assert_eq_count(&doc, r#"groups()/a"#, 2);
+        assert_eq_count(&doc, r#"groups()//img"#, 1);
+        assert_eq_count(&doc, r#"groups()/@aria-label"#, 2);
+        assert_eq_eval(&doc, r#"count(groups()/a)"#, 2.0);
+        assert_eq_eval(&doc, r#"groups()/a = 'Open Here!'"#, true);
+        assert_eq_count(&doc, r#"//div[1] | groups()"#, 3);
+        assert_eq_count(&doc, r#"//body[groups()/img]"#, 1);
+        assert_eq_count(&doc, r#"$divs/a"#, 2);
+
+        assert_eq_err(&doc, r#"groups()/"#, Error::TrailingSlash);
+    }
+
+    #[test]
+    fn parenthesized_expressions() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq!(attribute_values(&doc, "  (//div)[1]  ", "class"), ["test1"]);
+        assert_eq!(
+            attribute_values(&doc, "\n\t(//div)[last()]\n", "class"),
+            ["group2"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"(//a)[2]"#, "class"),
+            ["clickable1"]
+        );
+        // Positions of a step's predicates are per parent instead.
+        assert_eq!(attribute_values(&doc, r#"//a[2]"#, "class"), ["clickable2"]);
+        assert_eq!(
+            attribute_values(&doc, r#"(//a | //div)[1]"#, "class"),
+            ["test1"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"(//div)[@aria-label][2]"#, "class"),
+            ["group2"]
+        );
+        assert_eq!(
+            attribute_values(&doc, r#"(//div)[2]/a"#, "class"),
+            ["clickable1"]
+        );
+        assert_eq!(attribute_values(&doc, r#"((//div))"#, "class").len(), 3);
+
+        assert_eq_eval(&doc, r#"count((//div)[position() > 1])"#, 2.0);
+        assert_eq_eval(&doc, r#"(1 + 2) * 2"#, 6.0);
+        assert_eq_eval_to_string(&doc, r#"("a")"#, "a");
+
+        assert!(doc.evaluate(r#"()"#).is_err());
+        assert!(doc.evaluate(r#"(//div"#).is_err());
+        assert!(evaluate(&doc, r#"(1)[1]"#).unwrap().is_err());
+    }
+
+    #[test]
+    fn variables() {
+        let mut doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let anchors = doc.evaluate(r#"//a"#).unwrap().collect_nodes().unwrap();
+        let title = evaluate(&doc, r#"//title"#).unwrap().unwrap();
+
+        doc.set_variable("nodes", anchors);
+        doc.set_variable("title", title);
+        doc.set_variable("class", String::from("group2"));
+        doc.set_variable("n", 2.0);
+
+        assert_eq_eval(&doc, r#"count($nodes)"#, 4.0);
+        assert_eq_eval(&doc, r#"count($title)"#, 1.0);
+        assert_eq_count(&doc, r#"$nodes"#, 4);
+        assert_eq_eval_to_string(&doc, r#"string($title)"#, "Document");
+        assert_eq_count(&doc, r#"//div[@class = $class]"#, 1);
+        assert_eq_count(&doc, r#"//div[$n]"#, 1);
+        assert_eq_eval(&doc, r#"$nodes = 'Maybe'"#, true);
+
+        // The variable is checked again for each div, not continued from where the last one stopped.
+        assert_eq_eval(&doc, r#"count(//div[$nodes])"#, 3.0);
+        assert_eq_eval(&doc, r#"count(//a[$title])"#, 4.0);
+
+        assert_eq!(
+            parse_error(&doc, r#"count($missing)"#),
+            (
+                Error::UnknownVariable(String::from("missing")),
+                String::from("count("),
+                String::from("$missing)")
+            )
+        );
+    }
+
+    #[test]
+    fn nodeset_string_value() {
+        use crate::functions::{Args, Function};
+
+        // Every div, last first.
+        #[derive(Debug)]
+        struct ReversedDivs;
+
+        impl Function for ReversedDivs {
+            fn exec<'a>(&self, eval: &Evaluation, _: Args<'a>) -> Result<Value> {
+                let mut nodes = eval.document.evaluate(r#"//div"#)?.collect_nodes()?;
+                nodes.nodes.reverse();
+
+                Ok(Value::Nodeset(nodes))
+            }
+        }
+
+        let mut doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_eval_to_string(&doc, r#"concat(//div, "!")"#, "Testing 1!");
+        assert_eq_eval_to_string(&doc, r#"string(//div)"#, "Testing 1");
+
+        doc.register_function("reversed-divs", ReversedDivs);
+
+        // The first node in document order, not the first one returned.
+        assert_eq_eval_to_string(&doc, r#"concat(reversed-divs(), "!")"#, "Testing 1!");
+
+        let eval = Evaluation::new(&doc.root, &doc);
+        let value = ReversedDivs.exec(&eval, Args::new(&mut [])).unwrap();
+
+        assert_eq!(value.to_string(), "Testing 1");
+        assert_eq!(value.convert_to_string(), Ok(String::from("Testing 1")));
+        assert_eq!(
+            Value::Nodeset(Nodeset::new()).convert_to_string(),
+            Ok(String::new())
+        );
+    }
+
+    #[test]
+    fn concat_nodesets() {
+        let mut doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let group = evaluate(&doc, r#"string(//div[2])"#)
+            .unwrap()
+            .unwrap()
+            .string()
+            .unwrap();
+
+        assert_eq_eval_to_string(
+            &doc,
+            r#"concat(//div[1], "-", //div[2])"#,
+            format!("Testing 1-{group}"),
+        );
+        assert_eq_eval_to_string(
+            &doc,
+            r#"concat(//span/@class, "-", //a/@class)"#,
+            "test2-clickable1",
+        );
+        assert_eq_eval_to_string(&doc, r#"concat(//missing, "-", //span)"#, "-Testing 2");
+
+        // Each argument is the first node again for every context node.
+        assert_eq_count(&doc, r#"//span[concat(//span, "") = "Testing 2"]"#, 2);
+
+        let spans = doc.evaluate(r#"//span"#).unwrap().collect_nodes().unwrap();
+        doc.set_variable("spans", spans);
+        assert_eq_eval_to_string(&doc, r#"concat($spans, $spans)"#, "Testing 2Testing 2");
+    }
+
+    #[test]
+    fn union() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let nodes = doc
+            .evaluate(r#"//div | //a"#)
+            .unwrap()
+            .nodes()
+            .collect::<Result<Vec<Node>>>()
+            .unwrap();
+
+        let described = nodes
+            .iter()
+            .map(|node| {
+                let class = doc
+                    .evaluate_from(r#"@class"#, node)
+                    .unwrap()
+                    .next()
+                    .map(|v| v.unwrap().convert_to_string().unwrap())
+                    .unwrap_or_default();
+
+                format!("{}.{class}", node.name().unwrap().local)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            described,
+            vec![
+                "div.test1",
+                "a.",
+                "div.group1",
+                "a.clickable1",
+                "a.clickable2",
+                "div.group2",
+                "a.clickable1",
+            ]
+        );
+
+        for (i, node) in nodes.iter().enumerate() {
+            assert!(!nodes[i + 1..].contains(node), "Repeated {node:?}");
+        }
+
+        // The same nodes from both sides are only returned once.
+        assert_eq_count(&doc, r#"//div | //div"#, 3);
+        assert_eq_count(&doc, r#"//a | //div/a"#, 4);
+        assert_eq_count(&doc, r#"//div/a | //a"#, 4);
+        assert_eq_count(&doc, r#"//div/@class | //div/@class"#, 3);
+        assert_eq_count(&doc, r#"//div/@class | //div/@aria-label"#, 5);
+        assert_eq_eval_to_string(&doc, r#"//div/@aria-label | //div/@class"#, "test1");
+
+        // Each '|' of a chain adds to it.
+        assert_eq_count(&doc, r#"//h1 | //span | //div"#, 6);
+        assert_eq!(
+            evaluate_strings(&doc, r#"//span | //h1 | //div[@class = 'test1']"#),
+            ["Testing 1", "Testing 2", "Testing 3", "The Group is here!"]
+        );
+        assert_eq_count(&doc, r#"//div[h1 | a | span]"#, 2);
+
+        // Inside a predicate it's evaluated for each node.
+        assert_eq_count(&doc, r#"//div[h1 | a]"#, 2);
+        assert_eq_count(&doc, r#"//div[count(h1 | a) = 2]"#, 1);
+    }
+
+    #[test]
+    fn node_number_equality() {
+        let doc = parse_document(&mut Cursor::new(
+            r#"<x data-n="5"></x><x data-n=" 5.0 "></x><x data-n="6">5</x><x data-n="five"></x><input tabindex="0">"#,
+        ))
+        .unwrap();
+
+        assert_eq_count(&doc, r#"//x[@data-n=5]"#, 2);
+        assert_eq_count(&doc, r#"//x[5=@data-n]"#, 2);
+        assert_eq_count(&doc, r#"//x[@data-n=6]"#, 1);
+        assert_eq_count(&doc, r#"//x[@data-n!=5]"#, 2);
+        assert_eq_count(&doc, r#"//x[text()=5]"#, 1);
+        assert_eq_count(&doc, r#"//input[@tabindex=0]"#, 1);
+        assert_eq_count(&doc, r#"//input[@tabindex=-0]"#, 1);
+        // Strings which aren't numbers are NaN, which equals nothing.
+        assert_eq_count(&doc, r#"//x[@data-n=//missing + 1]"#, 0);
+    }
+
+    #[test]
+    fn text_equality() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_count(&doc, r#"//a[text()='Open Here!']"#, 1);
+        assert_eq_count(&doc, r#"//div[text()="Testing 1"]"#, 1);
+
+        let doc = parse_document(&mut Cursor::new(
+            r#"
+			<div>
+				<a class="indented">
+					Open Here!
+				</a>
+				<a>Open Here! Now</a>
+			</div>"#,
+        ))
+        .unwrap();
+
+        assert_eq_count(&doc, r#"//a[text()='Open Here!']"#, 1);
+        assert_eq_eval_to_string(&doc, r#"//a[text()='Open Here!']/@class"#, "indented");
+        // Only the ends are trimmed.
+        assert_eq_count(&doc, r#"//a[text()='Open']"#, 0);
+        // The string-value itself is untouched.
+        assert_eq_eval(
+            &doc,
+            r#"string(//a)"#,
+            Value::String("\n\t\t\t\t\tOpen Here!\n\t\t\t\t".into()),
+        );
+    }
+
+    #[test]
+    fn boolean_coercion() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_eval(&doc, r#"not("")"#, true);
+        assert_eq_eval(&doc, r#"not("x")"#, false);
+        assert_eq_eval(&doc, r#"not(0)"#, true);
+        assert_eq_eval(&doc, r#"not(2)"#, false);
+        assert_eq_eval(&doc, r#"not(//div)"#, false);
+        assert_eq_eval(&doc, r#"not(//table)"#, true);
+
+        assert_eq_eval(&doc, r#"boolean(2.5)"#, true);
+        assert_eq_eval(&doc, r#"boolean(0)"#, false);
+        assert_eq_eval(&doc, r#"boolean(-1)"#, true);
+        assert_eq_eval(&doc, r#"boolean(//table + 1)"#, false);
+        assert_eq_eval(&doc, r#"boolean("false")"#, true);
+        assert_eq_eval(&doc, r#"boolean("")"#, false);
+        assert_eq_eval(&doc, r#"boolean(//div)"#, true);
+        assert_eq_eval(&doc, r#"boolean(//table)"#, false);
+
+        assert_eq_eval(&doc, r#"2 and "x""#, true);
+        assert_eq_eval(&doc, r#"2 and """#, false);
+        assert_eq_eval(&doc, r#"0 or "x""#, true);
+        assert_eq_eval(&doc, r#"//table or //div"#, true);
+        assert_eq_eval(&doc, r#"//table and //div"#, false);
+
+        assert!(Value::String(String::from("x")).convert_to_boolean());
+        assert!(!Value::Number(f64::NAN).convert_to_boolean());
+    }
+
+    #[test]
+    fn unnamed_nodes() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_eval_to_string(&doc, r#"name(/)"#, "");
+        assert_eq_eval_to_string(&doc, r#"local-name(/)"#, "");
+        assert_eq_eval_to_string(&doc, r#"namespace-uri(/)"#, "");
+        assert_eq_eval_to_string(&doc, r#"name(//title/text())"#, "");
+        assert_eq_eval_to_string(&doc, r#"local-name(//title/text())"#, "");
+
+        assert_eq_eval_to_string(&doc, r#"name(//div)"#, "div");
+        assert_eq_eval_to_string(&doc, r#"local-name(//div)"#, "div");
+        assert_eq_eval_to_string(
+            &doc,
+            r#"namespace-uri(//div)"#,
+            "http://www.w3.org/1999/xhtml",
+        );
+
+        let doc = parse_document(&mut Cursor::new(r#"<p><!-- note --></p>"#)).unwrap();
+        assert_eq_eval_to_string(&doc, r#"name(//comment())"#, "");
+    }
+
+    #[test]
+    fn dynamic_attribute_names() {
+        let doc = parse_document(&mut Cursor::new(
+            r#"<body>
+                <div data-id="1" data-kind="card" class="a">One</div>
+                <div data-id="2" title="two">Two</div>
+                <span id="x" data-kind="label">Three</span>
+            </body>"#,
+        ))
+        .unwrap();
+
+        // Without an argument the name is the context node's, here the attribute's.
+        assert_eq!(
+            evaluate_strings(&doc, r#"//*/@*[local-name()='data-id']"#),
+            ["1", "2"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//*/@*[starts-with(local-name(), 'data-')]"#),
+            ["1", "card", "2", "label"]
+        );
+        assert_eq!(
+            evaluate_strings(&doc, r#"//*/@*[starts-with(name(), 'data-')]/.."#),
+            ["One", "Two", "Three"]
+        );
+        assert_eq_count(&doc, r#"//*[@*[local-name()='title']]"#, 1);
+        assert_eq_count(&doc, r#"//@*[not(starts-with(local-name(), 'data-'))]"#, 3);
+
+        assert_eq_eval_to_string(&doc, r#"local-name(//span/@id)"#, "id");
+        assert_eq_eval_to_string(&doc, r#"name(//span/@data-kind)"#, "data-kind");
+        assert_eq_eval_to_string(&doc, r#"namespace-uri(//span/@id)"#, "");
+        assert_eq_eval_to_string(&doc, r#"//span[local-name() = 'span']/@id"#, "x");
+        assert_eq_eval_to_string(&doc, r#"local-name(//missing)"#, "");
+    }
+
+    #[test]
+    fn context_node_comparisons() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        // Elements by their string-value.
+        assert_eq_count(&doc, r#"//div[.='Testing 1']"#, 1);
+        assert_eq_eval_to_string(&doc, r#"//*[.='Testing 2']/@class"#, "test2");
+        assert_eq_count(&doc, r#"//span[. != 'Testing 2']"#, 1);
+        assert_eq_count(&doc, r#"//div[.='Testing']"#, 0);
+        assert_eq_count(&doc, r#"//a[.='Maybe']"#, 1);
+        assert_eq_count(&doc, r#"//a[self::node()='Maybe']"#, 1);
+
+        // Attributes by their value.
+        assert_eq_count(&doc, r#"//@class[.='test1']"#, 1);
+        assert_eq_eval_to_string(&doc, r#"name(//@*[.='Come in!'])"#, "aria-label");
+        assert_eq_count(&doc, r#"//@class[. = 'clickable1']"#, 2);
+        assert_eq_count(&doc, r#"//div/@*[. != 'group1']"#, 4);
+
+        // Text by its contents.
+        assert_eq_count(&doc, r#"//span/text()[.='Testing 3']"#, 1);
+        assert_eq_count(&doc, r#"//text()[.='Open Here!']/parent::a"#, 1);
+
+        // Either side.
+        assert_eq_count(&doc, r#"//div['Testing 1'=.]"#, 1);
+    }
+
+    #[test]
+    fn substring() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_eval_to_string(&doc, r#"substring("12345", 2, 3)"#, "234");
+        // Missing length continues to the end.
+        assert_eq_eval_to_string(&doc, r#"substring("12345", 2)"#, "2345");
+        assert_eq_eval_to_string(&doc, r#"substring("hello", 1.5, 2.6)"#, "ell");
+        assert_eq_eval_to_string(&doc, r#"substring("12345", 0, 3)"#, "12");
+        assert_eq_eval_to_string(&doc, r#"substring("12345", -1, 3)"#, "1");
+        assert_eq_eval_to_string(&doc, r#"substring("12345", 2, -1)"#, "");
+        assert_eq_eval_to_string(&doc, r#"substring("12345", 2, 0)"#, "");
+        assert_eq_eval_to_string(&doc, r#"substring("12345", 10)"#, "");
+        // NaN from adding to an empty node-set.
+        assert_eq_eval_to_string(&doc, r#"substring("12345", 2, //missing + 1)"#, "");
+        assert_eq_eval_to_string(&doc, r#"substring("12345", //missing + 1)"#, "");
+        // An empty node-set is NaN as a number.
+        assert_eq_eval_to_string(&doc, r#"substring("12345", 2, //missing)"#, "");
+        assert_eq_eval_to_string(&doc, r#"substring("12345", //missing)"#, "");
+        assert_eq_eval_to_string(&doc, r#"substring("12345", //missing, 2)"#, "");
+        // Other arguments are converted like number() does.
+        assert_eq_eval_to_string(&doc, r#"substring("12345", "2", "3")"#, "234");
+        assert_eq_eval_to_string(&doc, r#"substring("12345", true(), 2)"#, "12");
+        // Characters, not bytes.
+        assert_eq_eval_to_string(&doc, r#"substring("héllo", 2, 2)"#, "él");
+        assert_eq_eval_to_string(&doc, r#"substring(//div, 9)"#, "1");
+    }
+
+    #[test]
+    fn multibyte_strings() {
+        let doc = parse_document(&mut Cursor::new("<body><p>café</p><p>👋🌍!</p></body>")).unwrap();
+
+        // Characters are counted, not bytes.
+        assert_eq_eval(&doc, r#"string-length("héllo")"#, 5.0);
+        assert_eq_eval(&doc, r#"string-length("👋🌍")"#, 2.0);
+        assert_eq_eval(&doc, r#"string-length(//p[1])"#, 4.0);
+        assert_eq_eval(&doc, r#"string-length(//p[2])"#, 3.0);
+        assert_eq_eval(&doc, r#"string-length("")"#, 0.0);
+
+        // Slicing inside a multibyte character doesn't panic.
+        assert_eq_eval_to_string(&doc, r#"substring("👋🌍!", 2)"#, "🌍!");
+        assert_eq_eval_to_string(&doc, r#"substring("👋🌍!", 2, 1)"#, "🌍");
+        assert_eq_eval_to_string(&doc, r#"substring(//p[1], 4)"#, "é");
+        assert_eq_eval_to_string(&doc, r#"substring(//p[1], 2, 2)"#, "af");
+        assert_eq_eval_to_string(&doc, r#"substring("ñandú", 0, 2)"#, "ñ");
+
+        // The two agree on where a string ends.
+        assert_eq_eval_to_string(&doc, r#"substring(//p[2], string-length(//p[2]))"#, "!");
+        assert_eq_eval_to_string(&doc, r#"substring("àé", string-length("àé") + 1)"#, "");
+    }
+
+    #[test]
+    fn empty_string_matching() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_eval(&doc, r#"starts-with("abc", "")"#, true);
+        assert_eq_eval(&doc, r#"starts-with("", "")"#, true);
+        assert_eq_eval(&doc, r#"starts-with("", "a")"#, false);
+        assert_eq_eval(&doc, r#"ends-with("abc", "")"#, true);
+        assert_eq_eval(&doc, r#"ends-with("", "")"#, true);
+        assert_eq_eval(&doc, r#"ends-with("abc", "bc")"#, true);
+        assert_eq_eval(&doc, r#"ends-with("abc", "ab")"#, false);
+        assert_eq_eval(&doc, r#"contains("abc", "")"#, true);
+        assert_eq_eval(&doc, r#"contains("", "")"#, true);
+        assert_eq_eval(&doc, r#"contains("", "x")"#, false);
+
+        // An empty node-set is an empty string.
+        assert_eq_eval(&doc, r#"contains(//missing, "")"#, true);
+        assert_eq_eval(&doc, r#"starts-with("abc", //missing)"#, true);
+        assert_eq_eval(&doc, r#"ends-with(//missing, "x")"#, false);
+    }
+
+    #[test]
+    fn contains_token() {
+        let doc = parse_document(&mut Cursor::new(
+            r#"<button class="btn btn-lg">1</button><button class="btnx">2</button><button class=" btn-primary  btn ">3</button>"#,
+        ))
+        .unwrap();
+
+        assert_eq_count(&doc, r#"//button[contains-token(@class, "btn")]"#, 2);
+        assert_eq_count(&doc, r#"//button[contains-token(@class, " btn-lg ")]"#, 1);
+        assert_eq_count(&doc, r#"//button[contains-token(@class, "btnx")]"#, 1);
+        assert_eq_count(&doc, r#"//button[contains-token(@class, "lg")]"#, 0);
+        assert_eq_count(&doc, r#"//button[contains-token(@id, "btn")]"#, 0);
+        // contains matches any part of the value.
+        assert_eq_count(&doc, r#"//button[contains(@class, "btn")]"#, 3);
+
+        assert_eq_eval(&doc, r#"contains-token("a b c", "b")"#, true);
+        assert_eq_eval(&doc, r#"contains-token("a b c", "")"#, false);
+        assert_eq_eval(&doc, r#"contains-token("", "a")"#, false);
+    }
+
+    #[test]
+    fn normalize_space() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_eval_to_string(&doc, "normalize-space(\"a\t\n  b\")", "a b");
+        assert_eq_eval_to_string(&doc, "normalize-space(\"\n\ta \t b\tc  \n\")", "a b c");
+        assert_eq_eval_to_string(&doc, r#"normalize-space("   ")"#, "");
+        assert_eq_eval_to_string(&doc, r#"normalize-space(//a[@class="clickable2"])"#, "");
+        assert_eq_eval_to_string(
+            &doc,
+            r#"normalize-space(//div[@class="group1"])"#,
+            "The Group is here! Don't click!",
+        );
+    }
+
+    #[test]
+    fn general_examples() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        // Simple
+
+        assert_eq_eval(&doc, r#"contains("abc123", "bc12")"#, true);
+        assert_eq_eval(&doc, r#"contains("abc123", "4")"#, false);
+
+        assert_eq_eval(&doc, r#"concat(true, "123")"#, Value::String("123".into()));
+        assert_eq_eval(&doc, r#"concat(false, "123")"#, Value::String("123".into()));
+        assert_eq_eval(&doc, r#"concat(1, "123")"#, Value::String("1123".into()));
+        assert_eq_eval(
+            &doc,
+            r#"concat("abc", "123")"#,
+            Value::String("abc123".into()),
+        );
+
+        assert_eq_eval(&doc, r#"starts-with("abc123", "abc")"#, true);
+        assert_eq_eval(&doc, r#"starts-with("123", 1)"#, true);
+
+        // TODO: Below doesn't work.
+
+        // assert_eq_eval(&doc, r#"substring-before("abc123", "1")"#, Value::String("abc".into()));
+
+        // assert_eq_eval(&doc, r#"substring-after("abc123", "c")"#, Value::String("123".into()));
+
+        // Document Lookups
+
+        assert_eq_eval(
+            &doc,
+            r#"//div[contains(text(), "Testing 1")]/@class"#,
+            Value::String("test1".into()),
+        );
+
+        // debug!("Examples");
+        // dbg!(doc.evaluate("//*[@id='rcTEST']//*[contains(text(), 'TEST Interactive')]/../button[2]"));
+        // dbg!(doc.evaluate("//*[@id='rcTEST']//*[contains(text(), 'TEST Interactive')]/..//*[contains(text(), 'Setting')]"));
+        // dbg!(doc.evaluate("//*[@id='rcTEST']//*[contains(text(), 'TEST Interactive')]/following-sibling::button"));
+        // dbg!(doc.evaluate("// address[@class='ng-scope ng-isolate-scope']//div[contains('Testing') and @id='msgTitle']"));
+        // dbg!(doc.evaluate("//*[@name='myForm']//table[@id='tbl_ testdm']/tbody/tr/td[6]/"));
+        // dbg!(doc.evaluate("input[@value='Open RFS']"));
+        // dbg!(doc.evaluate("//*[@title='Songs List Applet']//table//td[contains(text(),'Author')]"));
+        // dbg!(doc.evaluate("//*[@id='parameters']//*[@id='testUpdateTime']"));
+        // dbg!(doc.evaluate("//*[@id='MODEL/PLAN']/div[1]/div[2]/div[1]/div[1]/widget/section/div[1]/div/div[1]/div/div/button[1]"));
+        // dbg!(doc.evaluate("//*[contains(text(),'Watch Dial')]/../div/select[@data-ng-model='context.questions[subqts.subHandleSubId]']"));
+        // dbg!(doc.evaluate("//*[@id='RESEARCH/PLAN']//*[contains(@id, 'A4')]/../../following-sibling::div[1]/div[1]/span[1]/span[1]"));
+        // dbg!(doc.evaluate("//*[@id='ALARMDATA']//*[contains(@id, 'AFC2')]/../../preceding-sibling::div[1]/div[1]/span[1]/span[1]"));
+        // dbg!(doc.evaluate("//*[@id='RESEARCH/REVIEW']//widget/section/div[1]/div/div[2]/div[1]/div[3]/div[1]//span[@class='details']"));
+        // dbg!(doc.evaluate("//a[contains(.,'Parameter Data Manual Entry')]"));
+        // dbg!(doc.evaluate("//*[contains(@style,'display: block; top:')]//input[@name='daterangepicker_end']"));
+        // dbg!(doc.evaluate("//*[@id='dropdown-filter-serviceTools']/following-sibling::ul/descendant::a[text()='Notepad']"));
+        // dbg!(doc.evaluate("//*[@id='dropdown-filter-serviceTools']/following-sibling::ul/descendant::a[text()='Trigger Dashboard']"));
+        // dbg!(doc.evaluate("//h3[text()='Internal Debrief']"));
+        // dbg!(doc.evaluate("//h3[contains(text(),'Helium Level')]/following-sibling::div/label/input"));
+        // dbg!(doc.evaluate("//div[div[p[contains(text(),'Status')]]]/preceding-sibling::div/div/span[3]/span"));
+        // dbg!(doc.evaluate("//*[@id='COUPLING']//*[contains(text(),'COUPLE Trend')]/../div/select"));
+        // dbg!(doc.evaluate("//*[@id='ffaHeaderDropdown']//a[contains(text(),'Start Workflow')]"));
+    }
+
+    #[test]
+    fn general_errors() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_err(
+            &doc,
+            r#"//div[@class='test1'"#,
+            Error::ExpectedClosingBracket,
+        );
+
+        assert_eq_err(
+            &doc,
+            r#"::div"#,
+            Error::UnexpectedToken(ExprToken::LocationStep),
+        );
+        assert_eq_err(
+            &doc,
+            r#"div::"#,
+            Error::UnexpectedToken(ExprToken::LocationStep),
+        );
+        assert_eq_err(
+            &doc,
+            r#"//div/::a"#,
+            Error::UnexpectedToken(ExprToken::LocationStep),
+        );
+
+        // Where parsing stopped.
+        assert_eq!(
+            parse_error(&doc, r#"//div[@class="#),
+            (
+                Error::ExpectedRightHandExpression(Operator::Equal.into()),
+                String::from("//div[@class="),
+                String::new()
+            )
+        );
+        assert_eq!(
+            parse_error(&doc, r#"//div[1 2]"#),
+            (
+                Error::ExpectedClosingBracket,
+                String::from("//div[1"),
+                String::from(" 2]")
+            )
+        );
+        assert_eq!(
+            parse_error(&doc, r#"//div[$]"#),
+            (Error::Token, String::from("//div["), String::from("$]"))
+        );
+
+        // assert_eq_err(&doc, r#"contains("abc123")"#, Error::FunctionError("alloc::boxed::Box<dyn xpather::functions::Function>".to_string(), Box::new(Error::MissingFuncArgument)));
+    }
+
+    #[test]
+    fn function_parentheses() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        assert_eq_eval(&doc, r#"contains(substring("abcdef", 2), "bc")"#, true);
+        assert_eq_eval_to_string(&doc, r#"concat(substring("abc", 2), "d")"#, "bcd");
+        assert_eq_eval(&doc, r#"string-length(concat("(", ")"))"#, 2.0);
+        assert_eq_eval(&doc, r#"count(//div[contains(@class, "group")])"#, 2.0);
+
+        assert_eq!(
+            parse_error(&doc, r#"count(//div"#),
+            (
+                Error::UnbalancedParentheses,
+                String::from("count"),
+                String::from("(//div")
+            )
+        );
+        assert_eq!(
+            parse_error(&doc, r#"contains(substring("abc", 1), "a""#),
+            (
+                Error::UnbalancedParentheses,
+                String::from("contains"),
+                String::from(r#"(substring("abc", 1), "a""#)
+            )
+        );
+        assert_eq!(
+            parse_error(&doc, r#"count(//div))"#),
+            (
+                Error::UnbalancedParentheses,
+                String::from("count(//div)"),
+                String::from(")")
+            )
+        );
+    }
+
+    #[test]
+    fn strict_evaluation() {
+        let doc = parse_document(&mut Cursor::new(WEBPAGE)).unwrap();
+
+        let strict = |search: &str| {
+            doc.evaluate_strict(search)
+                .and_then(|mut v| v.next().transpose())
+                .map_err(|e| e.inner().clone())
+        };
+
+        // Fully parsed queries are the same as evaluate.
+        assert_eq!(strict(r#"6 div 2"#), Ok(Some(Value::Number(3.0))));
+        assert_eq!(
+            strict(r#"7 mod 4"#),
+            evaluate(&doc, r#"7 mod 4"#).transpose()
+        );
+        assert_eq!(
+            strict(r#"count(//div[@class and @aria-label])"#),
+            Ok(Some(Value::Number(2.0)))
+        );
+        assert_eq!(
+            strict(r#"//div[@class="test1"]/following-sibling::*[1]/@class"#)
+                .map(|v| v.map(|v| v.to_string())),
+            Ok(Some(String::from("test2")))
+        );
+
+        assert_eq!(strict(r#"1 + 2 + 3"#), Ok(Some(Value::Number(6.0))));
+        assert_eq!(strict(r#"10 - 2 - 3 + 1"#), Ok(Some(Value::Number(6.0))));
+
+        // Evaluate ignores whatever is left after the expression.
+        assert_eq_eval(&doc, r#"1 2"#, 1.0);
+        assert_eq!(
+            strict(r#"1 2"#),
+            Err(Error::UnexpectedToken(ExprToken::Number(2.0)))
+        );
+
+        assert_eq!(
+            strict(r#"count(//a | //span | //div)"#),
+            Ok(Some(Value::Number(9.0)))
+        );
+
+        // Each side of 'and' and 'or' is a full comparison, chained left to right.
+        assert_eq!(strict(r#"1 = 1 and 2 = 2"#), Ok(Some(Value::Boolean(true))));
+        assert_eq!(
+            strict(r#"1 = 1 and 2 = 3"#),
+            Ok(Some(Value::Boolean(false)))
+        );
+        assert_eq!(
+            strict(r#"1 = 2 or 2 = 3 or 3 = 3"#),
+            Ok(Some(Value::Boolean(true)))
+        );
+        assert_eq!(
+            strict(r#"1 = 1 and 2 = 2 and 3 = 4"#),
+            Ok(Some(Value::Boolean(false)))
+        );
+        assert_eq!(strict(r#"1 = 2 = false()"#), Ok(Some(Value::Boolean(true))));
+        assert_eq!(strict(r#"3 > 2 > 1"#), Ok(Some(Value::Boolean(false))));
+        assert_eq!(strict(r#"1 < 2 <= 1"#), Ok(Some(Value::Boolean(true))));
+        assert_eq_eval(&doc, r#"1 = 1 and 2 = 3"#, false);
+        assert_eq_count(
+            &doc,
+            r#"//div[@class = 'group1' and @aria-label = 'Watch Out!']"#,
+            1,
+        );
+        assert_eq_count(&doc, r#"//div[@class = 'test1' or @class = 'group2']"#, 2);
+        assert_eq_count(
+            &doc,
+            r#"//a[@class = 'clickable1' and . != 'Open Here!' or @class = 'clickable2']"#,
+            2,
+        );
+
+        assert_eq!(
+            doc.evaluate_strict(r#"1 + 2 2"#).err(),
+            Some(Error::Parse {
+                error: Box::new(Error::UnexpectedToken(ExprToken::Number(2.0))),
+                consumed: String::from("1 + 2"),
+                remaining: String::from(" 2"),
+            })
+        );
     }
 }
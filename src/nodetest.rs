@@ -8,6 +8,11 @@ use crate::{Evaluation, Node as DomNode, Nodeset};
 
 pub trait NodeTest: fmt::Debug {
     fn test(&self, context: &Evaluation, result: &mut Nodeset);
+
+    /// Is this the `node()` test.
+    fn is_any_node(&self) -> bool {
+        false
+    }
 }
 
 // TODO: Convert to markup5ever::QualName
@@ -155,10 +160,17 @@ impl Element {
 
 impl NodeTest for Element {
     fn test(&self, context: &Evaluation, result: &mut Nodeset) {
-        if let Some(name) = context.node.name() {
-            if context.node.is_element() && self.name_test.is_match(context, &name) {
-                result.add_node(context.node.clone());
-            }
+        if !context.node.is_element() {
+            return;
+        }
+
+        // An element whose DOM node was dropped no longer has a name to match.
+        let Some(name) = context.node.name() else {
+            return;
+        };
+
+        if self.name_test.is_match(context, &name) {
+            result.add_node(context.node.clone());
         }
     }
 }
@@ -169,8 +181,18 @@ pub struct Node;
 
 impl NodeTest for Node {
     fn test(&self, context: &Evaluation, result: &mut Nodeset) {
+        if context.options.skip_comments_and_pis
+            && (context.node.is_comment() || context.node.is_processing_instruction())
+        {
+            return;
+        }
+
         result.add_node(context.node.clone());
     }
+
+    fn is_any_node(&self) -> bool {
+        true
+    }
 }
 
 #[allow(missing_copy_implementations)]
@@ -1,3 +1,5 @@
+use std::sync::OnceLock;
+
 use regex::Regex;
 use tracing::error;
 
@@ -6,6 +8,9 @@ use crate::{Error, NameTest, Result};
 
 pub type Id<T> = (&'static str, T);
 
+// ExprWhitespace ::= (#x20 | #x9 | #xD | #xA)+
+const WHITESPACE: [char; 4] = [' ', '\t', '\r', '\n'];
+
 pub static SINGLE_CHAR_TOKENS: [Id<ExprToken>; 13] = [
     ("/", ExprToken::Operator(Operator::ForwardSlash)),
     ("(", ExprToken::LeftParen),
@@ -22,24 +27,25 @@ pub static SINGLE_CHAR_TOKENS: [Id<ExprToken>; 13] = [
     (",", ExprToken::Comma),
 ];
 
-pub static DOUBLE_CHAR_TOKENS: [Id<ExprToken>; 5] = [
+pub static DOUBLE_CHAR_TOKENS: [Id<ExprToken>; 6] = [
     ("<=", ExprToken::Operator(Operator::LessThanOrEqual)),
     (">=", ExprToken::Operator(Operator::GreaterThanOrEqual)),
     ("!=", ExprToken::Operator(Operator::DoesNotEqual)),
     ("//", ExprToken::Operator(Operator::DoubleForwardSlash)),
     ("..", ExprToken::ParentNode),
+    // Only seen when it isn't directly after an axis name (see parse_axes).
+    ("::", ExprToken::LocationStep),
 ];
 
-// TODO: Disabled for now.
-// Reasons:
-//     '/html/body/div[1]/following::*'    'div' being converted
-//     '/html/body/*'                      '*' being converted
-pub static NAMED_OPERATORS: [Id<ExprToken>; 3] = [
+pub static NAMED_OPERATORS: [Id<ExprToken>; 2] = [
     ("and", ExprToken::Operator(Operator::And)),
     ("or", ExprToken::Operator(Operator::Or)),
-    // ("mod", ExprToken::Operator(Operator::Mod)),
-    // ("div", ExprToken::Operator(Operator::Div)),
-    ("*", ExprToken::Operator(Operator::Star)),
+];
+
+// Only operators after an operand, otherwise they're element names. ex: '/html/body/div[1]'
+pub static NAMED_MULTIPLY_OPERATORS: [Id<ExprToken>; 2] = [
+    ("mod", ExprToken::Operator(Operator::Mod)),
+    ("div", ExprToken::Operator(Operator::Div)),
 ];
 
 pub static AXES: [Id<AxisName>; 13] = [
@@ -75,6 +81,7 @@ pub type ParseResult = Option<(usize, ExprToken)>;
 pub struct Tokenizer {
     xpath: String,
     pos: usize,
+    prev_token: Option<ExprToken>,
 }
 
 impl Tokenizer {
@@ -82,24 +89,57 @@ impl Tokenizer {
         Tokenizer {
             xpath: xpath.into(),
             pos: 0,
+            prev_token: None,
         }
     }
 
+    /// Start tokenizing a new query, re-using the allocation of the previous one.
+    pub fn reset<S: Into<String>>(&mut self, query: S) {
+        self.xpath.clear();
+        self.xpath.push_str(&query.into());
+        self.pos = 0;
+        self.prev_token = None;
+    }
+
+    pub fn query(&self) -> &str {
+        &self.xpath
+    }
+
+    /// Byte offset into the query of the next token, including any whitespace before it.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Is there nothing but whitespace left to tokenize.
     pub fn is_finished(&self) -> bool {
-        self.xpath.len() <= self.pos
+        self.xpath[self.pos..]
+            .trim_start_matches(WHITESPACE)
+            .is_empty()
     }
 
     pub fn next_token(&mut self) -> TokenResult {
         let remaining_xpath = {
             // Remove Whitespace from start
             let bytes = self.xpath.as_bytes();
-            while bytes[self.pos] == b' ' {
+            while bytes
+                .get(self.pos)
+                .is_some_and(|&b| WHITESPACE.contains(&(b as char)))
+            {
                 self.pos += 1;
             }
 
             &self.xpath[self.pos..]
         };
 
+        // Checked up front so an invalid processing-instruction target is an error instead of a name test.
+        let node_type = match Tokenizer::parse_node_types(remaining_xpath) {
+            Ok(v) => v,
+            Err(e) => {
+                self.pos = self.xpath.len();
+                return Err(e);
+            }
+        };
+
         let found = None
             // Double Characters
             .or_else(|| Tokenizer::parse_token_array(remaining_xpath, &DOUBLE_CHAR_TOKENS))
@@ -111,12 +151,16 @@ impl Tokenizer {
             .or_else(|| Tokenizer::parse_numbers(remaining_xpath))
             // Current Node
             .or_else(|| Tokenizer::parse_current_node(remaining_xpath))
+            // Multiply Operator
+            .or_else(|| self.parse_multiply_operator(remaining_xpath))
+            // 'div' and 'mod'
+            .or_else(|| self.parse_named_multiply_operator(remaining_xpath))
             // Named Operators
             .or_else(|| Tokenizer::parse_token_array(remaining_xpath, &NAMED_OPERATORS))
             // Axis Specifier
             .or_else(|| Tokenizer::parse_axes(remaining_xpath))
             // Node Type
-            .or_else(|| Tokenizer::parse_node_types(remaining_xpath))
+            .or(node_type)
             // Function Call
             .or_else(|| Tokenizer::parse_function_call(remaining_xpath))
             // Variable Reference
@@ -126,6 +170,7 @@ impl Tokenizer {
 
         if let Some((inc, token)) = found {
             self.pos += inc;
+            self.prev_token = Some(token.clone());
             Ok(token)
         } else {
             self.pos = self.xpath.len();
@@ -154,9 +199,7 @@ impl Tokenizer {
         // "[^"]*" | '[^']*'
         let as_bytes = rem_path.as_bytes();
 
-        if as_bytes[0] == b'"' || as_bytes[0] == b'\'' {
-            let quote_type = if as_bytes[0] == b'"' { b'"' } else { b'\'' };
-
+        if let Some(&quote_type @ (b'"' | b'\'')) = as_bytes.first() {
             let mut end_pos = 1;
 
             while as_bytes.len() > end_pos && as_bytes[end_pos] != quote_type {
@@ -166,8 +209,7 @@ impl Tokenizer {
             // Add 1 to include last quote
             end_pos += 1;
 
-            if as_bytes.len() >= end_pos && end_pos - 1 != 1 && as_bytes[end_pos - 1] == quote_type
-            {
+            if as_bytes.len() >= end_pos && as_bytes[end_pos - 1] == quote_type {
                 // Add 1 to start, remove 1 from end to remove both quotes.
                 Some((
                     end_pos,
@@ -193,7 +235,7 @@ impl Tokenizer {
 
         let as_bytes = rem_path.as_bytes();
 
-        if numbers.contains(&as_bytes[0]) {
+        if as_bytes.first().is_some_and(|v| numbers.contains(v)) {
             let mut used_decimal = false;
             let mut end_pos = 0;
 
@@ -225,13 +267,48 @@ impl Tokenizer {
     }
 
     fn parse_current_node(rem_path: &str) -> ParseResult {
-        if rem_path.get(0..1).expect("parse_current_node") == "." {
+        if rem_path.starts_with('.') {
             Some((1, ExprToken::Period))
         } else {
             None
         }
     }
 
+    // If there is a preceding token and it's not one of '@', '::', '(', '[', ',' or an Operator,
+    // then a '*' must be recognized as a MultiplyOperator and an NCName must be recognized as an OperatorName.
+    fn prev_token_ends_operand(&self) -> bool {
+        matches!(
+            self.prev_token,
+            Some(
+                ExprToken::Number(_)
+                    | ExprToken::Literal(_)
+                    | ExprToken::RightParen
+                    | ExprToken::RightBracket
+                    | ExprToken::Period
+                    | ExprToken::ParentNode
+                    | ExprToken::NameTest(_)
+                    | ExprToken::NodeType(_)
+                    | ExprToken::VariableReference(_)
+            )
+        )
+    }
+
+    fn parse_multiply_operator(&self, rem_path: &str) -> ParseResult {
+        if rem_path.starts_with('*') && self.prev_token_ends_operand() {
+            Some((1, ExprToken::Operator(Operator::Star)))
+        } else {
+            None
+        }
+    }
+
+    fn parse_named_multiply_operator(&self, rem_path: &str) -> ParseResult {
+        if self.prev_token_ends_operand() {
+            Tokenizer::parse_token_array(rem_path, &NAMED_MULTIPLY_OPERATORS)
+        } else {
+            None
+        }
+    }
+
     fn parse_axes(rem_path: &str) -> ParseResult {
         if let Some(mut parsed) = Tokenizer::parse_token_array(rem_path, &AXES) {
             if rem_path.len() >= parsed.0 + 2 && &rem_path[parsed.0..parsed.0 + 2] == "::" {
@@ -243,7 +320,7 @@ impl Tokenizer {
         None
     }
 
-    fn parse_node_types(rem_path: &str) -> ParseResult {
+    fn parse_node_types(rem_path: &str) -> Result<Option<(usize, ExprToken)>> {
         if let Some((mut last_pos, results)) = Tokenizer::parse_token_array(rem_path, &NODE_TYPES) {
             if let Some((size, inner_str)) = Tokenizer::find_function_parenth(&rem_path[last_pos..])
             {
@@ -251,21 +328,33 @@ impl Tokenizer {
 
                 let mut node_type: NodeType = match results.into() {
                     Some(v) => v,
-                    None => return None,
+                    None => return Ok(None),
                 };
 
                 // Check to see if it's a Processing Instruction. If so, check the parentheses
                 if let NodeType::ProcessingInstruction(inner) = &mut node_type {
-                    *inner = inner_str.map(|i| i.to_string());
+                    *inner = Tokenizer::parse_processing_instruction_target(inner_str)?;
                 }
 
-                return Some((last_pos, ExprToken::NodeType(node_type)));
+                return Ok(Some((last_pos, ExprToken::NodeType(node_type))));
             } else {
-                return Some((last_pos, results));
+                return Ok(Some((last_pos, results)));
             }
         }
 
-        None
+        Ok(None)
+    }
+
+    // 'processing-instruction' '(' Literal? ')'
+    fn parse_processing_instruction_target(inner: Option<&str>) -> Result<Option<String>> {
+        let Some(inner) = inner.map(str::trim).filter(|v| !v.is_empty()) else {
+            return Ok(None);
+        };
+
+        match Tokenizer::parse_literal(inner) {
+            Some((size, ExprToken::Literal(target))) if size == inner.len() => Ok(Some(target)),
+            _ => Err(Error::Token),
+        }
     }
 
     // QName	   	::= Prefix ':' LocalPart | LocalPart
@@ -278,7 +367,8 @@ impl Tokenizer {
         // Argument		::= Expr
 
         // xml.txt: NameStartChar
-        let reg = Regex::new(r#"^[a-zA-Z0-9:_-]+"#).unwrap();
+        static FUNCTION_NAME: OnceLock<Regex> = OnceLock::new();
+        let reg = FUNCTION_NAME.get_or_init(|| Regex::new(r#"^[a-zA-Z0-9:_-]+"#).unwrap());
 
         if let Some(found) = reg.find(rem_path) {
             if Tokenizer::find_function_parenth(&rem_path[found.end()..]).is_some() {
@@ -294,7 +384,8 @@ impl Tokenizer {
 
     fn parse_variable_ref(rem_path: &str) -> ParseResult {
         // '$' QName
-        let reg = Regex::new(r#"^\$[a-zA-Z0-9:_]+"#).unwrap();
+        static VARIABLE_REF: OnceLock<Regex> = OnceLock::new();
+        let reg = VARIABLE_REF.get_or_init(|| Regex::new(r#"^\$[a-zA-Z0-9:_]+"#).unwrap());
 
         if let Some(found) = reg.find(rem_path) {
             // Capture QName
@@ -309,12 +400,8 @@ impl Tokenizer {
 
     fn parse_name_test(rem_path: &str) -> ParseResult {
         // '*' | NCName ':' '*' | QName
-        let bytes = rem_path.as_bytes();
-
-        // TODO: This is never going to be called since
-        // TODO: parse_token_array defines it as an Operator.
-        // *
-        if bytes[0] == b'*' {
+        // * (parse_multiply_operator has already ruled out multiplication)
+        if rem_path.starts_with('*') {
             Some((
                 1,
                 ExprToken::NameTest(NameTest {
@@ -323,7 +410,8 @@ impl Tokenizer {
                 }),
             ))
         } else {
-            let reg = Regex::new(r#"^[a-zA-Z0-9_]+:\*"#).unwrap();
+            static PREFIX_WILDCARD: OnceLock<Regex> = OnceLock::new();
+            let reg = PREFIX_WILDCARD.get_or_init(|| Regex::new(r#"^[a-zA-Z0-9_]+:\*"#).unwrap());
 
             // NCName:*
             if let Some(found) = reg.find(rem_path) {
@@ -346,7 +434,10 @@ impl Tokenizer {
                 // debug!("{}", String::from_utf16_lossy(&valid));
 
                 // Prefix ':' LocalPart | LocalPart
-                let reg = Regex::new(r#"(^[a-zA-Z0-9_-]+:?(?:[a-zA-Z0-9_-]+)?)"#).unwrap();
+                static QNAME: OnceLock<Regex> = OnceLock::new();
+                let reg = QNAME.get_or_init(|| {
+                    Regex::new(r#"(^[a-zA-Z0-9_-]+(?::[a-zA-Z0-9_-]+)?)"#).unwrap()
+                });
 
                 if let Some(found) = reg.find(rem_path) {
                     let opts = rem_path[0..found.end()].split(':').collect::<Vec<&str>>();
@@ -375,27 +466,34 @@ impl Tokenizer {
         }
     }
 
+    // Finds the ')' matching the '(' at the start. Nested parentheses and ones inside literals are skipped.
     fn find_function_parenth(rem_path: &str) -> Option<(usize, Option<&str>)> {
         let bytes = rem_path.as_bytes();
 
-        if bytes.len() >= 2 && bytes[0] == b'(' {
-            let mut inner_size = 0;
+        if bytes.first() != Some(&b'(') {
+            return None;
+        }
 
-            while inner_size < bytes.len() && bytes[inner_size] != b')' {
-                inner_size += 1;
-            }
+        let mut depth = 0;
+        let mut quote_type = None;
 
-            // Add 1 to capture ")"
-            inner_size += 1;
+        for (index, &byte) in bytes.iter().enumerate() {
+            match (quote_type, byte) {
+                (Some(quote), _) if quote == byte => quote_type = None,
+                (Some(_), _) => (),
+                (None, b'"' | b'\'') => quote_type = Some(byte),
+                (None, b'(') => depth += 1,
+                (None, b')') => {
+                    depth -= 1;
 
-            if rem_path.len() >= inner_size {
-                let inner_str = if inner_size == 2 {
-                    None
-                } else {
-                    Some(&rem_path[1..inner_size - 1])
-                };
+                    if depth == 0 {
+                        let inner_str = &rem_path[1..index];
 
-                return Some((inner_size, inner_str));
+                        // Add 1 to capture ")"
+                        return Some((index + 1, (!inner_str.is_empty()).then_some(inner_str)));
+                    }
+                }
+                _ => (),
             }
         }
 
@@ -2,14 +2,18 @@ use std::io::{Error as IoErrorBase, ErrorKind};
 
 use thiserror::Error as ThisError;
 
-use crate::ExprToken;
+use crate::{AxisName, ExprToken};
 
 pub type Result<I> = std::result::Result<I, Error>;
 
 #[derive(Debug, Clone, PartialEq, ThisError)]
 pub enum Error {
-    #[error("IO Error: {0:?}")]
-    Io(ErrorKind),
+    #[error("IO Error: {message}")]
+    Io {
+        kind: ErrorKind,
+        /// The `io::Error` message, which is more than its kind.
+        message: String,
+    },
 
     #[error("Token Error")]
     Token,
@@ -21,6 +25,8 @@ pub enum Error {
     ExpectedRightHandExpression(ExprToken),
     #[error("Unexpected Token {0:?}")]
     UnexpectedToken(ExprToken),
+    #[error("Expected Closing Bracket")]
+    ExpectedClosingBracket,
     #[error("Invalid Value {0:?}")]
     InvalidValue(ValueError),
     #[error("Cannot convert Node into Value")]
@@ -35,11 +41,40 @@ pub enum Error {
     MissingFuncArgument,
     #[error("Unable to find Value")]
     UnableToFindValue,
+    #[error("Expected a single result, found multiple")]
+    MultipleResults,
+    #[error("Unbalanced Parentheses")]
+    UnbalancedParentheses,
+    #[error("Unknown Variable ${0}")]
+    UnknownVariable(String),
+    #[error("Unsupported Axis {0:?}")]
+    UnsupportedAxis(AxisName),
+    #[error("{error} (parsed {consumed:?}, remaining {remaining:?})")]
+    Parse {
+        error: Box<Error>,
+        /// The start of the query which was parsed before the failure.
+        consumed: String,
+        /// The rest of the query, starting where parsing failed.
+        remaining: String,
+    },
+}
+
+impl Error {
+    /// The underlying error, without any parse position.
+    pub fn inner(&self) -> &Error {
+        match self {
+            Error::Parse { error, .. } => error.inner(),
+            error => error,
+        }
+    }
 }
 
 impl From<IoErrorBase> for Error {
     fn from(err: IoErrorBase) -> Error {
-        Error::Io(err.kind())
+        Error::Io {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
     }
 }
 
@@ -0,0 +1,476 @@
+// Tracks which bytes of the source each node was parsed from.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::rc::{Rc, Weak};
+
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::{
+    BufferQueue, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerResult,
+};
+use html5ever::tree_builder::{
+    ElementFlags, NextParserState, NodeOrText, QuirksMode, Tracer, TreeBuilder, TreeSink,
+};
+use markup5ever::{Attribute, ExpandedName, LocalName, QualName};
+use markup5ever_rcdom::{Handle, NodeData, RcDom, WeakHandle};
+
+/// Byte ranges in the source, keyed by the node they were parsed into.
+pub(crate) type SourceSpans = HashMap<NodeKey, Range<usize>>;
+
+/// A node compared by identity.
+///
+/// Holding a weak handle keeps the node's address from being given to another node once it's dropped,
+/// so a lookup never finds a different node's span.
+#[derive(Clone)]
+pub(crate) struct NodeKey(WeakHandle);
+
+impl NodeKey {
+    pub(crate) fn new(handle: &Handle) -> Self {
+        Self(Rc::downgrade(handle))
+    }
+}
+
+impl PartialEq for NodeKey {
+    fn eq(&self, other: &Self) -> bool {
+        Weak::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for NodeKey {}
+
+impl Hash for NodeKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_ptr().hash(state)
+    }
+}
+
+/// Parses an HTML document, recording the bytes of `source` each element and comment was parsed from.
+///
+/// The tokenizer only reports lines, so the source is fed to it a piece at a time, with every `<` and `>`
+/// on its own. A tag is emitted while its `>` is being fed, which gives where it ends.
+pub(crate) fn parse(source: &str) -> (RcDom, SourceSpans) {
+    let builder = TreeBuilder::new(SpanSink::new(), Default::default());
+    let mut tokenizer = Tokenizer::new(SpanTokens::new(source, builder), Default::default());
+    let mut queue = BufferQueue::new();
+
+    for chunk in chunks(source) {
+        queue.push_back(StrTendril::from_slice(&source[chunk.clone()]));
+        tokenizer.sink.chunk = chunk;
+
+        while let TokenizerResult::Script(_) = tokenizer.feed(&mut queue) {}
+    }
+
+    tokenizer.sink.chunk = source.len()..source.len();
+    tokenizer.end();
+
+    tokenizer.sink.finish()
+}
+
+// Runs of text, with each '<' and '>' by itself.
+fn chunks(source: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = 0;
+
+    std::iter::from_fn(move || {
+        let rest = source
+            .as_bytes()
+            .get(start..)
+            .filter(|rest| !rest.is_empty())?;
+
+        let len = match rest.iter().position(|&b| b == b'<' || b == b'>') {
+            Some(0) => 1,
+            Some(index) => index,
+            None => rest.len(),
+        };
+
+        start += len;
+
+        Some(start - len..start)
+    })
+}
+
+/// Passes tokens on to the tree builder, working out the bytes each one came from.
+///
+/// A start tag's element and a comment span their token. An element ends with the end tag that closes it.
+/// One closed without its end tag, or whose end tag doesn't close it (like `</body>`),
+/// ends with the last token that added anything inside it.
+struct SpanTokens<'a> {
+    source: &'a str,
+    builder: TreeBuilder<Handle, SpanSink>,
+    // The part of the source being fed to the tokenizer.
+    chunk: Range<usize>,
+    // Where the next tag's '<' is searched from, after any text already emitted.
+    tag_search: usize,
+    starts: SourceSpans,
+    ends: HashMap<NodeKey, usize>,
+}
+
+impl<'a> SpanTokens<'a> {
+    fn new(source: &'a str, builder: TreeBuilder<Handle, SpanSink>) -> Self {
+        Self {
+            source,
+            builder,
+            chunk: 0..0,
+            tag_search: 0,
+            starts: HashMap::new(),
+            ends: HashMap::new(),
+        }
+    }
+
+    fn token_range(&mut self, token: &Token) -> Range<usize> {
+        match token {
+            Token::TagToken(_) | Token::CommentToken(_) | Token::DoctypeToken(_) => {
+                let end = self.chunk.end;
+                let start = self.source[self.tag_search..end]
+                    .find('<')
+                    .map_or(self.chunk.start, |index| self.tag_search + index);
+
+                self.tag_search = end;
+
+                start..end
+            }
+
+            Token::CharacterTokens(_) | Token::NullCharacterToken => {
+                self.tag_search = self.tag_search.max(self.chunk.start);
+
+                self.chunk.clone()
+            }
+
+            Token::EOFToken | Token::ParseError(_) => self.chunk.clone(),
+        }
+    }
+
+    // Every node the tree builder is holding on to, including the open elements.
+    fn held_nodes(&self) -> Vec<Handle> {
+        let tracer = HeldNodes(RefCell::new(Vec::new()));
+        self.builder.trace_handles(&tracer);
+
+        tracer.0.into_inner()
+    }
+
+    fn finish(self) -> (RcDom, SourceSpans) {
+        let Self {
+            builder,
+            starts,
+            ends,
+            ..
+        } = self;
+
+        let sink = builder.sink;
+        let mut node_ends = HashMap::new();
+
+        // Each node is visited a second time once its children have been.
+        let mut stack = vec![(sink.dom.document.clone(), false)];
+
+        while let Some((node, visited_children)) = stack.pop() {
+            let children = children_of(&node);
+
+            if !visited_children {
+                stack.push((node, true));
+                stack.extend(children.into_iter().map(|child| (child, false)));
+                continue;
+            }
+
+            let key = NodeKey::new(&node);
+
+            let end = ends.get(&key).copied().or_else(|| {
+                children
+                    .iter()
+                    .filter_map(|child| node_ends.get(&NodeKey::new(child)).copied())
+                    .chain(sink.content_ends.get(&key).copied())
+                    .chain(starts.get(&key).map(|start| start.end))
+                    .max()
+            });
+
+            if let Some(end) = end {
+                node_ends.insert(key, end);
+            }
+        }
+
+        let spans = starts
+            .into_iter()
+            .map(|(key, start)| {
+                let end = node_ends
+                    .get(&key)
+                    .map_or(start.end, |&end| end.max(start.end));
+                (key, start.start..end)
+            })
+            .collect();
+
+        (sink.dom, spans)
+    }
+}
+
+impl TokenSink for SpanTokens<'_> {
+    type Handle = Handle;
+
+    fn process_token(&mut self, token: Token, line_number: u64) -> TokenSinkResult<Handle> {
+        let range = self.token_range(&token);
+
+        let tag = match &token {
+            Token::TagToken(tag) => Some((tag.kind, tag.name.clone())),
+            _ => None,
+        };
+
+        let is_comment = matches!(token, Token::CommentToken(_));
+        let held = match &tag {
+            Some((TagKind::EndTag, _)) => self.held_nodes(),
+            _ => Vec::new(),
+        };
+
+        self.builder.sink.token_end = range.end;
+        self.builder.sink.created.clear();
+
+        let result = self.builder.process_token(token, line_number);
+
+        match tag {
+            // Elements the tree builder adds on its own are created first.
+            Some((TagKind::StartTag, name)) => {
+                let created = self.builder.sink.created.iter().rev();
+
+                if let Some(element) = created.clone().find(|node| is_named(node, &name)) {
+                    self.starts.insert(NodeKey::new(element), range);
+                }
+            }
+
+            Some((TagKind::EndTag, name)) => {
+                let still_held = self
+                    .held_nodes()
+                    .iter()
+                    .map(NodeKey::new)
+                    .collect::<HashSet<_>>();
+
+                let closed = held
+                    .iter()
+                    .rev()
+                    .filter(|node| !still_held.contains(&NodeKey::new(node)))
+                    .find(|node| is_named(node, &name));
+
+                if let Some(element) = closed {
+                    self.ends.insert(NodeKey::new(element), range.end);
+                }
+            }
+
+            None if is_comment => {
+                if let Some(comment) = self.builder.sink.created.last() {
+                    self.starts.insert(NodeKey::new(comment), range);
+                }
+            }
+
+            None => (),
+        }
+
+        result
+    }
+
+    fn end(&mut self) {
+        self.builder.end()
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        self.builder
+            .adjusted_current_node_present_but_not_in_html_namespace()
+    }
+}
+
+struct HeldNodes(RefCell<Vec<Handle>>);
+
+impl Tracer for HeldNodes {
+    type Handle = Handle;
+
+    fn trace_handle(&self, node: &Handle) {
+        self.0.borrow_mut().push(node.clone());
+    }
+}
+
+fn is_named(handle: &Handle, local: &LocalName) -> bool {
+    matches!(&handle.data, NodeData::Element { name, .. } if &name.local == local)
+}
+
+// A `<template>`'s children are in its contents.
+fn children_of(handle: &Handle) -> Vec<Handle> {
+    let contents = match &handle.data {
+        NodeData::Element {
+            template_contents, ..
+        } => template_contents.borrow().clone(),
+        _ => None,
+    };
+
+    let children = contents
+        .as_ref()
+        .unwrap_or(handle)
+        .children
+        .borrow()
+        .clone();
+
+    children
+}
+
+fn parent_of(handle: &Handle) -> Option<Handle> {
+    let parent = handle.parent.take();
+    handle.parent.set(parent.clone());
+
+    parent?.upgrade()
+}
+
+/// Builds an `RcDom`, remembering the nodes created for each token and the last token
+/// that added anything to each node.
+pub(crate) struct SpanSink {
+    dom: RcDom,
+    // The end of the token being processed.
+    token_end: usize,
+    created: Vec<Handle>,
+    content_ends: HashMap<NodeKey, usize>,
+}
+
+impl SpanSink {
+    fn new() -> Self {
+        Self {
+            dom: RcDom::default(),
+            token_end: 0,
+            created: Vec::new(),
+            content_ends: HashMap::new(),
+        }
+    }
+
+    fn created(&mut self, handle: Handle) -> Handle {
+        self.created.push(handle.clone());
+        handle
+    }
+
+    fn added_to(&mut self, handle: &Handle) {
+        self.content_ends
+            .insert(NodeKey::new(handle), self.token_end);
+    }
+}
+
+impl TreeSink for SpanSink {
+    type Handle = Handle;
+    type Output = Self;
+
+    fn finish(self) -> Self {
+        self
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.dom.parse_error(msg)
+    }
+
+    fn get_document(&mut self) -> Handle {
+        self.dom.get_document()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Handle) -> ExpandedName<'a> {
+        self.dom.elem_name(target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Handle {
+        let handle = self.dom.create_element(name, attrs, flags);
+        self.created(handle)
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Handle {
+        let handle = self.dom.create_comment(text);
+        self.created(handle)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Handle {
+        self.dom.create_pi(target, data)
+    }
+
+    fn append(&mut self, parent: &Handle, child: NodeOrText<Handle>) {
+        self.added_to(parent);
+        self.dom.append(parent, child)
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Handle,
+        prev_element: &Handle,
+        child: NodeOrText<Handle>,
+    ) {
+        // Added before the element when it has a parent, otherwise to the previous element.
+        match parent_of(element) {
+            Some(parent) => self.added_to(&parent),
+            None => self.added_to(prev_element),
+        }
+
+        self.dom
+            .append_based_on_parent_node(element, prev_element, child)
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.dom
+            .append_doctype_to_document(name, public_id, system_id)
+    }
+
+    fn mark_script_already_started(&mut self, node: &Handle) {
+        self.dom.mark_script_already_started(node)
+    }
+
+    fn pop(&mut self, node: &Handle) {
+        self.dom.pop(node)
+    }
+
+    fn get_template_contents(&mut self, target: &Handle) -> Handle {
+        self.dom.get_template_contents(target)
+    }
+
+    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+        self.dom.same_node(x, y)
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.dom.set_quirks_mode(mode)
+    }
+
+    fn append_before_sibling(&mut self, sibling: &Handle, new_node: NodeOrText<Handle>) {
+        if let Some(parent) = parent_of(sibling) {
+            self.added_to(&parent);
+        }
+
+        self.dom.append_before_sibling(sibling, new_node)
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+        self.dom.add_attrs_if_missing(target, attrs)
+    }
+
+    fn associate_with_form(
+        &mut self,
+        target: &Handle,
+        form: &Handle,
+        nodes: (&Handle, Option<&Handle>),
+    ) {
+        self.dom.associate_with_form(target, form, nodes)
+    }
+
+    fn remove_from_parent(&mut self, target: &Handle) {
+        self.dom.remove_from_parent(target)
+    }
+
+    fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+        self.added_to(new_parent);
+        self.dom.reparent_children(node, new_parent)
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &Handle) -> bool {
+        self.dom.is_mathml_annotation_xml_integration_point(handle)
+    }
+
+    fn complete_script(&mut self, node: &Handle) -> NextParserState {
+        self.dom.complete_script(node)
+    }
+}
@@ -60,6 +60,17 @@ impl AxisName {
             _ => PrincipalNodeType::Element,
         }
     }
+
+    /// Does the axis go backwards through the document. Positions along it count from the nearest node.
+    pub fn is_reverse(&self) -> bool {
+        matches!(
+            self,
+            AxisName::Ancestor
+                | AxisName::AncestorOrSelf
+                | AxisName::Preceding
+                | AxisName::PrecedingSibling
+        )
+    }
 }
 
 // PartialEq<markup5ever::Attribute> for NameTest
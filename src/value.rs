@@ -1,15 +1,19 @@
 use std::cell::Cell;
+use std::collections::HashMap;
 use std::fmt;
+use std::ops::Range;
 use std::rc::Rc;
 
 use html5ever::serialize;
-use markup5ever::{Attribute as DomAttribute, QualName};
+use markup5ever::{namespace_url, ns, Attribute as DomAttribute, QualName};
 use markup5ever_rcdom::{
-    Handle as NodeHandle, NodeData, SerializableHandle, WeakHandle as WeakNodeHandle,
+    Handle as NodeHandle, Node as DomNode, NodeData, SerializableHandle,
+    WeakHandle as WeakNodeHandle,
 };
 
 use crate::factory::ProduceIter;
 use crate::result::{Result, ValueError};
+use crate::span::NodeKey;
 use crate::{Document, Error};
 
 #[derive(Debug, Clone)]
@@ -18,6 +22,8 @@ pub enum Value {
     Number(f64),
     String(String),
     Node(Node),
+    /// Several nodes as one value. Expressions produce nodes one at a time, this is for functions returning a node-set.
+    Nodeset(Nodeset),
 }
 
 impl Value {
@@ -27,6 +33,7 @@ impl Value {
             Value::Number(v) => !v.is_nan(),
             Value::String(v) => !v.is_empty(),
             Value::Node(_) => true,
+            Value::Nodeset(v) => !v.is_empty(),
         }
     }
 
@@ -48,6 +55,26 @@ impl Value {
         }
     }
 
+    pub fn as_nodeset(&self) -> Result<&Nodeset> {
+        match self {
+            Self::Nodeset(s) => Ok(s),
+            _ => Err(ValueError::Nodeset.into()),
+        }
+    }
+
+    pub fn is_nodeset(&self) -> bool {
+        matches!(self, Self::Nodeset(_))
+    }
+
+    /// A single node is returned as a node-set containing only it.
+    pub fn into_nodeset(self) -> Result<Nodeset> {
+        match self {
+            Self::Nodeset(s) => Ok(s),
+            Self::Node(s) => Ok(Nodeset { nodes: vec![s] }),
+            _ => Err(ValueError::Nodeset.into()),
+        }
+    }
+
     pub fn boolean(&self) -> Result<bool> {
         match self {
             &Self::Boolean(v) => Ok(v),
@@ -57,6 +84,48 @@ impl Value {
         }
     }
 
+    /// The XPath `=` comparison. Unlike `==`, two nodes are equal when their string-values are
+    /// rather than only when they're the same node, and any node equals its string-value.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Self::Node(left), Self::Node(right)) => {
+                match (left.get_string_value(), right.get_string_value()) {
+                    (Ok(left), Ok(right)) => left == right,
+                    _ => false,
+                }
+            }
+
+            (Self::Node(node), Self::String(value)) | (Self::String(value), Self::Node(node)) => {
+                self == other || node.get_string_value().is_ok_and(|v| v == *value)
+            }
+
+            _ => self == other,
+        }
+    }
+
+    /// Convert the `Value` into a `bool` using the XPath `boolean()` rules.
+    ///
+    /// - Numbers are true unless they're zero or `NaN`.
+    /// - Strings are true unless they're empty.
+    /// - Nodes are always true, node-sets unless they're empty.
+    pub fn convert_to_boolean(self) -> bool {
+        match self {
+            Value::Number(v) => v != 0.0 && !v.is_nan(),
+            _ => self.is_something(),
+        }
+    }
+
+    /// Convert the `Value` into a number using the XPath `number()` rules.
+    ///
+    /// - Booleans are `1` when true and `0` when false.
+    /// - Strings, nodes and node-sets are their string-value as a number, or `NaN` if it isn't one.
+    pub fn convert_to_number(self) -> Result<f64> {
+        Ok(match self {
+            Value::Boolean(_) | Value::Number(_) => self.number()?,
+            value => string_to_number(&value.convert_to_string()?),
+        })
+    }
+
     pub fn number(&self) -> Result<f64> {
         match *self {
             Self::Boolean(v) => Ok(if v { 1.0 } else { 0.0 }),
@@ -79,24 +148,97 @@ impl Value {
         }
     }
 
-    /// Change non-string `Value` to a `String`
+    /// Convert the `Value` into a `String` using the XPath `string()` rules.
+    ///
+    /// This is the coercion used whenever a function expects a string argument.
+    /// - Booleans become `"true"` or `"false"`.
+    /// - Numbers are written without an exponent, integers without a decimal point,
+    ///   and `NaN`, `Infinity` and `-Infinity` by name. Negative zero is `"0"`.
+    /// - Nodes become their string-value.
+    /// - Node-sets become the string-value of their first node, or `""` when empty.
     pub fn convert_to_string(self) -> Result<String> {
         Ok(match self {
-            Value::Boolean(_) => String::new(),
-            Value::Number(v) => v.to_string(),
+            Value::Boolean(v) => v.to_string(),
+            Value::Number(v) => number_to_string(v),
             Value::String(v) => v,
             Value::Node(v) => v.get_string_value()?,
+            Value::Nodeset(v) => match v.first_in_document_order() {
+                Some(node) => node.get_string_value()?,
+                None => String::new(),
+            },
         })
     }
 }
 
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Boolean(v) => write!(f, "{v}"),
+            Value::Number(v) => f.write_str(&number_to_string(*v)),
+            Value::String(v) => f.write_str(v),
+            Value::Node(v) => f.write_str(&v.get_string_value().unwrap_or_default()),
+            Value::Nodeset(v) => f.write_str(
+                &v.first_in_document_order()
+                    .and_then(|node| node.get_string_value().ok())
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+/// XPath 1.0 number to string conversion.
+pub(crate) fn number_to_string(value: f64) -> String {
+    if value.is_nan() {
+        String::from("NaN")
+    } else if value.is_infinite() {
+        if value.is_sign_positive() {
+            String::from("Infinity")
+        } else {
+            String::from("-Infinity")
+        }
+    } else if value == 0.0 {
+        // Also catches negative zero.
+        String::from("0")
+    } else {
+        // Rust never uses an exponent and prints the shortest digits which round-trip.
+        value.to_string()
+    }
+}
+
+/// XPath 1.0 string to number conversion. Anything which isn't an optionally negative decimal is `NaN`.
+pub(crate) fn string_to_number(value: &str) -> f64 {
+    let value = value.trim();
+
+    let digits = value.strip_prefix('-').unwrap_or(value);
+
+    let is_number = !digits.is_empty()
+        && digits != "."
+        && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && digits.matches('.').count() <= 1;
+
+    if is_number {
+        value.parse().unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    }
+}
+
 impl PartialEq for Value {
     fn eq(&self, other: &Value) -> bool {
         match (self, other) {
             (Self::Number(v1), Self::Number(v2)) => v1 == v2,
             (Self::Boolean(v1), Self::Boolean(v2)) => v1 == v2,
             (Self::String(v1), Self::String(v2)) => v1 == v2,
+            // The same node. Value::equals is the XPath comparison of their string-values.
             (Self::Node(set1), Self::Node(set2)) => set1 == set2,
+            (Self::Nodeset(set1), Self::Nodeset(set2)) => set1.nodes == set2.nodes,
+
+            // Node == Number
+            (Self::Node(node), Self::Number(value)) | (Self::Number(value), Self::Node(node)) => {
+                node.get_string_value()
+                    .map(|v| string_to_number(&v) == *value)
+                    .unwrap_or_default()
+            }
 
             // Node == String
             (Self::Node(node), Self::String(value)) | (Self::String(value), Self::Node(node)) => {
@@ -107,12 +249,14 @@ impl PartialEq for Value {
                     match node {
                         Node::Attribute(attr) => attr.value() == value,
 
+                        // Leading and trailing whitespace of a text node is ignored since
+                        // indented HTML puts newlines around most text.
                         Node::Text(handle) => {
                             let upgrade = handle.upgrade().unwrap();
                             if let NodeData::Text { contents } = &upgrade.data {
                                 contents
                                     .try_borrow()
-                                    .map(|v| v.as_ref() == value)
+                                    .map(|v| v.trim() == value)
                                     .unwrap_or_default()
                             } else {
                                 false
@@ -129,6 +273,54 @@ impl PartialEq for Value {
     }
 }
 
+/// Nodes are serialized as their string-value. Wrap the `Value` in [`AsHtml`] to serialize their outer HTML instead.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Value {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serialize_value(self, serializer, false)
+    }
+}
+
+/// Serializes the nodes of a `Value` as their outer HTML.
+#[cfg(feature = "serde")]
+pub struct AsHtml<'a>(pub &'a Value);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for AsHtml<'_> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serialize_value(self.0, serializer, true)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn serialize_value<S: serde::Serializer>(
+    value: &Value,
+    serializer: S,
+    as_html: bool,
+) -> std::result::Result<S::Ok, S::Error> {
+    // The root has no HTML of its own so it falls back to the string-value.
+    let node_to_string = |node: &Node| {
+        let html = if as_html { node.as_simple_html() } else { None };
+
+        html.or_else(|| node.get_string_value().ok())
+            .unwrap_or_default()
+    };
+
+    match value {
+        Value::Boolean(v) => serializer.serialize_bool(*v),
+        Value::Number(v) => serializer.serialize_f64(*v),
+        Value::String(v) => serializer.serialize_str(v),
+        Value::Node(v) => serializer.serialize_str(&node_to_string(v)),
+        Value::Nodeset(v) => serializer.collect_seq(v.nodes.iter().map(node_to_string)),
+    }
+}
+
 impl From<bool> for Value {
     fn from(val: bool) -> Self {
         Value::Boolean(val)
@@ -153,6 +345,54 @@ impl From<Node> for Value {
     }
 }
 
+impl From<Nodeset> for Value {
+    fn from(val: Nodeset) -> Self {
+        Value::Nodeset(val)
+    }
+}
+
+impl From<Vec<Node>> for Value {
+    fn from(val: Vec<Node>) -> Self {
+        Value::Nodeset(val.into())
+    }
+}
+
+/// The same as [`Value::convert_to_string`].
+impl TryFrom<Value> for String {
+    type Error = Error;
+
+    fn try_from(val: Value) -> Result<Self> {
+        val.convert_to_string()
+    }
+}
+
+/// The same as [`Value::number`], only booleans and numbers convert.
+impl TryFrom<Value> for f64 {
+    type Error = Error;
+
+    fn try_from(val: Value) -> Result<Self> {
+        val.number()
+    }
+}
+
+/// The same as [`Value::convert_to_boolean`], it never fails.
+impl TryFrom<Value> for bool {
+    type Error = Error;
+
+    fn try_from(val: Value) -> Result<Self> {
+        Ok(val.convert_to_boolean())
+    }
+}
+
+/// The same as [`Value::into_node`].
+impl TryFrom<Value> for Node {
+    type Error = Error;
+
+    fn try_from(val: Value) -> Result<Self> {
+        val.into_node()
+    }
+}
+
 #[derive(Clone)]
 pub struct Attribute {
     pub parent: WeakNodeHandle,
@@ -200,6 +440,19 @@ impl Attribute {
     }
 }
 
+/// What kind of node a [`Node`] is, without its handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    Root,
+    DocType,
+    Element,
+    Attribute,
+    Text,
+    Comment,
+    ProcessingInstruction,
+    Namespace,
+}
+
 // TODO: Convert to
 // pub struct Node(WeakNodeHandle);
 // - No way to know if it's an Attribute though.
@@ -216,6 +469,19 @@ pub enum Node {
 }
 
 impl Node {
+    pub fn kind(&self) -> NodeKind {
+        match self {
+            Node::Root(_) => NodeKind::Root,
+            Node::DocType(_) => NodeKind::DocType,
+            Node::Element(_) => NodeKind::Element,
+            Node::Attribute(_) => NodeKind::Attribute,
+            Node::Text(_) => NodeKind::Text,
+            Node::Comment(_) => NodeKind::Comment,
+            Node::ProcessingInstruction(_) => NodeKind::ProcessingInstruction,
+            Node::Namespace(_) => NodeKind::Namespace,
+        }
+    }
+
     pub fn enum_name(&self) -> String {
         match self {
             Node::DocType(_) => "DocType".into(),
@@ -253,6 +519,10 @@ impl Node {
         matches!(self, Node::Comment(_))
     }
 
+    pub fn is_doctype(&self) -> bool {
+        matches!(self, Node::DocType(_))
+    }
+
     pub fn is_processing_instruction(&self) -> bool {
         matches!(self, Node::ProcessingInstruction(_))
     }
@@ -263,8 +533,27 @@ impl Node {
 
     pub fn value(&self) -> Result<Value> {
         match self {
+            // The string-value of the root node and of an element node is the
+            // concatenation of the string-values of all text node descendants in document order.
+            Node::Root(handle) => {
+                let mut value = String::new();
+                push_descendant_text(handle, &mut value);
+                Ok(Value::String(value))
+            }
+
+            Node::Element(node) => {
+                let handle = node.upgrade().ok_or(Error::CannotConvertNodeToValue)?;
+
+                let mut value = String::new();
+                push_descendant_text(&handle, &mut value);
+                Ok(Value::String(value))
+            }
+
             Node::Attribute(attr) => Ok(Value::String(attr.value().to_string())),
 
+            // Not an XPath node type. It has no text so its value is empty.
+            Node::DocType(_) => Ok(Value::String(String::new())),
+
             Node::Text(node) => {
                 if let NodeData::Text { contents } = &node.upgrade().unwrap().data {
                     Ok(Value::String(contents.borrow().to_string()))
@@ -310,74 +599,206 @@ impl Node {
         }
     }
 
+    /// The attributes of an element as attribute nodes, the same ones `@*` selects.
+    /// Anything else has none.
+    pub fn attribute_nodes(&self) -> Vec<Node> {
+        match self {
+            Node::Element(weak) if weak.upgrade().is_some() => Attribute::from_node(weak)
+                .unwrap_or_default()
+                .into_iter()
+                .map(Node::Attribute)
+                .collect(),
+
+            _ => Vec::new(),
+        }
+    }
+
     pub fn parent(&self) -> Option<Node> {
-        // TODO: Fix. Example. The Root element would get classified as an Node::Element instead of Node::Root.
         match self {
-            Node::Attribute(attr) => attr
-                .parent
-                .upgrade()
-                .and_then(|node| get_opt_node_from_cell(&node.parent).map(Node::Element)),
-            Node::DocType(_) | Node::Namespace(_) | Node::Root(_) => None,
-            Node::Element(weak) => weak
-                .upgrade()
-                .and_then(|node| get_opt_node_from_cell(&node.parent).map(Node::Element)),
-            Node::Text(weak) => weak
-                .upgrade()
-                .and_then(|node| get_opt_node_from_cell(&node.parent).map(Node::Text)),
-            Node::Comment(weak) => weak
-                .upgrade()
-                .and_then(|node| get_opt_node_from_cell(&node.parent).map(Node::Comment)),
-            Node::ProcessingInstruction(weak) => weak.upgrade().and_then(|node| {
-                get_opt_node_from_cell(&node.parent).map(Node::ProcessingInstruction)
-            }),
+            Node::Root(_) | Node::Namespace(_) => None,
+
+            // The element holding the attribute.
+            Node::Attribute(attr) => attr.parent.upgrade().map(Node::from),
+
+            _ => {
+                let handle = self.handle()?;
+                let parent = parent_handle(&handle)?;
+
+                // Converting the owned handle keeps the Document as Node::Root.
+                Some(Node::from(parent))
+            }
         }
     }
 
+    /// The position of the node among its parent's children, starting at 0.
+    /// The root and attributes aren't children of anything.
+    pub fn index_in_parent(&self) -> Option<usize> {
+        let node = self.as_handle()?;
+        let parent = parent_handle(&node)?;
+
+        child_index(&parent, &node)
+    }
+
+    /// The parent, grandparent and so on up to the top-most node.
+    pub fn ancestors(&self) -> impl Iterator<Item = Node> {
+        std::iter::successors(self.parent(), Node::parent)
+    }
+
+    /// The top-most node reached by following the parents. For a node inside a document that's its root.
+    pub fn root(&self) -> Node {
+        self.ancestors().last().unwrap_or_else(|| self.clone())
+    }
+
+    /// The child nodes. A `<template>` element's children are the ones inside its contents.
+    ///
+    /// Panics if the node's document was dropped, use [`Node::try_children`] to check for it instead.
     pub fn children(&self) -> Vec<Node> {
+        self.try_children()
+            .expect("the node's document was dropped")
+    }
+
+    /// The child nodes, or `None` if the node's document was dropped.
+    pub fn try_children(&self) -> Option<Vec<Node>> {
         match self {
-            Node::Root(handle) => {
-                let node = handle.as_ref();
+            // Not part of the tree, they have no children.
+            Node::Attribute(_) | Node::Namespace(_) => Some(Vec::new()),
+
+            _ => {
+                let node = with_template_contents(self.handle()?);
 
-                node.children.borrow().iter().map(|c| c.into()).collect()
+                let children = node.children.borrow().iter().map(|c| c.into()).collect();
+
+                Some(children)
             }
+        }
+    }
 
-            Node::Text(handle)
-            | Node::Comment(handle)
-            | Node::DocType(handle)
-            | Node::Element(handle) => {
-                let node = handle.upgrade().unwrap();
+    /// Lazily iterate the children. Unlike `children` nothing is collected up front.
+    pub fn children_iter(&self) -> impl Iterator<Item = Node> {
+        let handle = match self {
+            Node::Root(_)
+            | Node::Text(_)
+            | Node::Comment(_)
+            | Node::DocType(_)
+            | Node::Element(_) => self.handle().map(with_template_contents),
 
-                let borrow = node.children.borrow();
+            _ => None,
+        };
 
-                borrow.iter().map(|c| c.into()).collect()
-            }
+        let mut index = 0;
 
-            _ => unimplemented!("Node::children(\"{}\")", self.enum_name()),
-        }
+        std::iter::from_fn(move || {
+            let child = Node::from(handle.as_ref()?.children.borrow().get(index)?);
+
+            index += 1;
+
+            Some(child)
+        })
     }
 
     pub fn get_child(&self, index: usize) -> Option<Node> {
+        self.children_iter().nth(index)
+    }
+
+    /// The nodes after this one which share its parent, in document order.
+    pub fn following_siblings(&self) -> Vec<Node> {
         match self {
-            Node::Root(handle) => {
-                let node = handle.as_ref();
+            Node::Root(_) | Node::Attribute(_) | Node::Namespace(_) => Vec::new(),
+            _ => following_nodes_from_parent(self),
+        }
+    }
+
+    /// The nodes before this one which share its parent, in document order.
+    pub fn preceding_siblings(&self) -> Vec<Node> {
+        match self {
+            Node::Root(_) | Node::Attribute(_) | Node::Namespace(_) => Vec::new(),
+            _ => preceding_nodes_from_parent(self),
+        }
+    }
 
-                let children = node.children.borrow();
+    /// The nearest element after this one which shares its parent, skipping text and comments.
+    pub fn next_sibling_element(&self) -> Option<Node> {
+        self.following_siblings()
+            .into_iter()
+            .find(|node| node.is_element())
+    }
 
-                Some(children.get(index)?.into())
+    /// The nearest element before this one which shares its parent, skipping text and comments.
+    pub fn previous_sibling_element(&self) -> Option<Node> {
+        self.preceding_siblings()
+            .into_iter()
+            .rev()
+            .find(|node| node.is_element())
+    }
+
+    /// Copy the node and its descendants into an [`OwnedNode`], which can outlive the document.
+    ///
+    /// Only the parts of a dropped node which are still reachable are copied.
+    pub fn to_owned_subtree(&self) -> OwnedNode {
+        // Each copy stays on the stack, with the children left to copy, until they've all been added to it.
+        let mut stack = vec![(
+            self.to_owned_node(),
+            self.try_children().unwrap_or_default().into_iter(),
+        )];
+
+        loop {
+            let (_, children) = stack.last_mut().unwrap();
+
+            if let Some(child) = children.next() {
+                let children = child.try_children().unwrap_or_default().into_iter();
+                stack.push((child.to_owned_node(), children));
+                continue;
             }
 
-            Node::Text(handle)
-            | Node::Comment(handle)
-            | Node::DocType(handle)
-            | Node::Element(handle) => {
-                let node = handle.upgrade()?;
+            let (copied, _) = stack.pop().unwrap();
 
-                let children = node.children.borrow();
+            match stack.last_mut() {
+                Some((parent, _)) => parent.children.push(copied),
+                None => return copied,
+            }
+        }
+    }
 
-                Some(children.get(index)?.into())
+    // Copy the node without its children.
+    fn to_owned_node(&self) -> OwnedNode {
+        let name = match self {
+            Node::Element(_) => self.tag_name(),
+            Node::Attribute(attr) => Some(attr.name_string()),
+            Node::DocType(_) => self.doctype_name(),
+            Node::ProcessingInstruction(_) => self.target(),
+            _ => None,
+        };
+
+        let value = match self {
+            Node::Attribute(attr) => Some(attr.value().to_string()),
+            Node::Text(_) | Node::Comment(_) | Node::ProcessingInstruction(_) => {
+                self.handle().and_then(|handle| match &handle.data {
+                    NodeData::Text { contents } => Some(contents.borrow().to_string()),
+                    NodeData::Comment { contents }
+                    | NodeData::ProcessingInstruction { contents, .. } => {
+                        Some(contents.to_string())
+                    }
+                    _ => None,
+                })
             }
+            _ => None,
+        };
+
+        let attributes = match self {
+            Node::Element(weak) if weak.upgrade().is_some() => Attribute::from_node(weak)
+                .unwrap_or_default()
+                .iter()
+                .map(|attr| (attr.name_string(), attr.value().to_string()))
+                .collect(),
+            _ => Vec::new(),
+        };
 
-            _ => unimplemented!("Node::children(\"{}\")", self.enum_name()),
+        OwnedNode {
+            kind: self.kind(),
+            name,
+            value,
+            attributes,
+            children: Vec::new(),
         }
     }
 
@@ -391,11 +812,22 @@ impl Node {
                 }
             }
 
-            Node::Attribute(attr) => {
-                if let NodeData::Element { name, .. } = &attr.parent.upgrade()?.data {
-                    Some(name.clone())
+            Node::Attribute(attr) => Some(attr.name().clone()),
+
+            _ => None,
+        }
+    }
+
+    /// The local name of an element. Names in the HTML namespace are lowercased.
+    pub fn tag_name(&self) -> Option<String> {
+        match self {
+            Node::Element(_) => {
+                let name = self.name()?;
+
+                if name.ns == ns!(html) {
+                    Some((*name.local).to_ascii_lowercase())
                 } else {
-                    None
+                    Some(name.local.to_string())
                 }
             }
 
@@ -403,6 +835,18 @@ impl Node {
         }
     }
 
+    /// The name given in a `<!DOCTYPE name>` declaration.
+    pub fn doctype_name(&self) -> Option<String> {
+        match self {
+            Node::DocType(weak) => match &weak.upgrade()?.data {
+                NodeData::Doctype { name, .. } => Some(name.to_string()),
+                _ => None,
+            },
+
+            _ => None,
+        }
+    }
+
     pub fn target(&self) -> Option<String> {
         match self {
             Node::ProcessingInstruction(node) => {
@@ -421,6 +865,79 @@ impl Node {
         unimplemented!("Node::prefix()");
     }
 
+    /// Find the namespace uri bound to `prefix` in scope of this node.
+    pub fn lookup_namespace_uri(&self, prefix: &str) -> Option<String> {
+        let mut handle = self.handle();
+
+        while let Some(node) = handle {
+            if let Some((_, uri)) = namespace_declarations(&node)
+                .into_iter()
+                .find(|(p, _)| p == prefix)
+            {
+                return Some(uri);
+            }
+
+            handle = parent_handle(&node);
+        }
+
+        None
+    }
+
+    /// Find the prefix bound to the namespace `uri` in scope of this node.
+    pub fn lookup_prefix(&self, uri: &str) -> Option<String> {
+        let mut handle = self.handle();
+
+        while let Some(node) = handle {
+            if let Some((prefix, _)) = namespace_declarations(&node)
+                .into_iter()
+                .find(|(_, u)| u == uri)
+            {
+                return Some(prefix);
+            }
+
+            handle = parent_handle(&node);
+        }
+
+        None
+    }
+
+    /// Is this the very same node as `other`. Equal string-values aren't enough, see [`Value::equals`].
+    pub fn same_node(&self, other: &Node) -> bool {
+        match (self, other) {
+            (Node::Root(left), Node::Root(right)) => Rc::ptr_eq(left, right),
+
+            (Node::Root(_), _) | (_, Node::Root(_)) => false,
+
+            // Attributes only hold their element so the name is needed to tell them apart.
+            (Node::Attribute(left), Node::Attribute(right)) => {
+                left.parent.ptr_eq(&right.parent) && left.attr.name == right.attr.name
+            }
+
+            (Node::Attribute(_), _) | (_, Node::Attribute(_)) => false,
+
+            _ => match (self.inner_weak(), other.inner_weak()) {
+                (Some(left), Some(right)) => left.ptr_eq(right),
+                _ => false,
+            },
+        }
+    }
+
+    /// The `markup5ever_rcdom` handle of the node, if its DOM is still around.
+    /// Attributes aren't DOM nodes of their own so they have none.
+    pub fn as_handle(&self) -> Option<NodeHandle> {
+        match self {
+            Node::Attribute(_) => None,
+            _ => self.handle(),
+        }
+    }
+
+    pub(crate) fn handle(&self) -> Option<NodeHandle> {
+        match self {
+            Node::Root(handle) => Some(handle.clone()),
+            _ => self.inner_weak()?.upgrade(),
+        }
+    }
+
     pub fn inner_weak(&self) -> Option<&WeakNodeHandle> {
         match self {
             Node::Root(..) => None,
@@ -441,6 +958,78 @@ impl Node {
     ) -> Result<ProduceIter<'a>> {
         doc.evaluate_from(search, self)
     }
+
+    /// The bytes of the source this node was parsed from, if `doc` was parsed with
+    /// [`parse_document_with_spans`](crate::parse_document_with_spans).
+    ///
+    /// Only elements and comments have a span. An element's runs from its start tag to its end tag.
+    /// Without an end tag, it ends after the last thing parsed inside it.
+    pub fn source_span(&self, doc: &Document) -> Option<Range<usize>> {
+        let handle = self.as_handle()?;
+        doc.source_spans.get(&NodeKey::new(&handle)).cloned()
+    }
+}
+
+/// A read-only copy of a node and its descendants which doesn't reference the document.
+///
+/// Created with [`Node::to_owned_subtree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedNode {
+    pub kind: NodeKind,
+    /// The tag name of an element, the name of an attribute or doctype and the target of a processing-instruction.
+    pub name: Option<String>,
+    /// The contents of a text, comment or processing-instruction node and the value of an attribute.
+    pub value: Option<String>,
+    /// The name and value of each attribute of an element.
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<OwnedNode>,
+}
+
+// Dropped without recursing, a deep copy would overflow the stack.
+impl Drop for OwnedNode {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+impl OwnedNode {
+    /// The value of the attribute named `name`.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attr, _)| attr == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The XPath string-value, the same as [`Node::get_string_value`] returned before copying.
+    pub fn text(&self) -> String {
+        match self.kind {
+            NodeKind::Root | NodeKind::Element => {
+                let mut value = String::new();
+                self.push_descendant_text(&mut value);
+                value
+            }
+
+            _ => self.value.clone().unwrap_or_default(),
+        }
+    }
+
+    fn push_descendant_text(&self, value: &mut String) {
+        // Last to first so they're taken in document order.
+        let mut stack = self.children.iter().rev().collect::<Vec<_>>();
+
+        while let Some(node) = stack.pop() {
+            match node.kind {
+                NodeKind::Text => value.push_str(node.value.as_deref().unwrap_or_default()),
+                NodeKind::Element => stack.extend(node.children.iter().rev()),
+                _ => (),
+            }
+        }
+    }
 }
 
 impl From<&NodeHandle> for Node {
@@ -487,14 +1076,80 @@ impl From<NodeHandle> for Node {
 
 impl PartialEq for Node {
     fn eq(&self, other: &Node) -> bool {
-        if self.is_root() || other.is_root() {
-            return self.is_root() == other.is_root();
+        self.same_node(other)
+    }
+}
+
+// Namespaces declared on an element as (prefix, uri). The default namespace has an empty prefix.
+fn namespace_declarations(handle: &NodeHandle) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+
+    if let NodeData::Element { name, attrs, .. } = &handle.data {
+        for attr in attrs.borrow().iter() {
+            let attr_name = &attr.name;
+
+            if attr_name.prefix.as_deref() == Some("xmlns") {
+                found.push((attr_name.local.to_string(), attr.value.to_string()));
+            } else if attr_name.prefix.is_none() {
+                // HTML documents keep the declaration as a plain attribute name.
+                if &*attr_name.local == "xmlns" {
+                    found.push((String::new(), attr.value.to_string()));
+                } else if let Some(prefix) = attr_name.local.strip_prefix("xmlns:") {
+                    found.push((prefix.to_string(), attr.value.to_string()));
+                }
+            }
         }
 
-        match (self.inner_weak(), other.inner_weak()) {
-            (Some(left), Some(right)) => left.ptr_eq(right),
-            _ => false,
+        // XML parsing binds the prefix on the element name itself.
+        if let Some(prefix) = &name.prefix {
+            found.push((prefix.to_string(), name.ns.to_string()));
+        }
+    }
+
+    found
+}
+
+// The first declaration of the prefix in document order, searching the handle and its descendants.
+pub(crate) fn find_namespace_uri(handle: &NodeHandle, prefix: &str) -> Option<String> {
+    let mut stack = vec![handle.clone()];
+
+    while let Some(node) = stack.pop() {
+        if let Some((_, uri)) = namespace_declarations(&node)
+            .into_iter()
+            .find(|(p, _)| p == prefix)
+        {
+            return Some(uri);
         }
+
+        // Last to first so they're searched in document order.
+        stack.extend(node.children.borrow().iter().rev().cloned());
+    }
+
+    None
+}
+
+fn push_descendant_text(handle: &NodeHandle, value: &mut String) {
+    let mut stack = vec![handle.clone()];
+
+    while let Some(node) = stack.pop() {
+        if let NodeData::Text { contents } = &node.data {
+            value.push_str(&contents.borrow());
+            continue;
+        }
+
+        let node = with_template_contents(node);
+
+        // Last to first so they're taken in document order.
+        stack.extend(
+            node.children
+                .borrow()
+                .iter()
+                .rev()
+                .filter(|child| {
+                    matches!(child.data, NodeData::Text { .. } | NodeData::Element { .. })
+                })
+                .cloned(),
+        );
     }
 }
 
@@ -524,17 +1179,13 @@ fn find_nodes_from_parent<F: Fn(usize, usize) -> bool>(node: &Node, f_capture: F
         let parent = weak.upgrade().expect("dangling weak pointer");
         node.parent.set(Some(weak));
 
-        let children = parent.children.borrow();
-
-        let i = match children
-            .iter()
-            .enumerate()
-            .find(|&(_, child)| Rc::ptr_eq(child, &node))
-        {
-            Some((i, _)) => i,
+        let i = match child_index(&parent, &node) {
+            Some(i) => i,
             None => return Vec::new(),
         };
 
+        let children = parent.children.borrow();
+
         children
             .iter()
             .enumerate()
@@ -546,6 +1197,14 @@ fn find_nodes_from_parent<F: Fn(usize, usize) -> bool>(node: &Node, f_capture: F
     }
 }
 
+fn child_index(parent: &NodeHandle, node: &NodeHandle) -> Option<usize> {
+    with_template_contents(parent.clone())
+        .children
+        .borrow()
+        .iter()
+        .position(|child| Rc::ptr_eq(child, node))
+}
+
 pub fn compare_nodes(left_upgrade: &NodeHandle, right_upgrade: &NodeHandle) -> bool {
     let matched = match (&left_upgrade.data, &right_upgrade.data) {
         (
@@ -652,20 +1311,27 @@ impl fmt::Debug for Node {
         match self {
             Node::Root(weak) => f.debug_tuple("Root").field(&weak).finish(),
 
-            Node::Attribute(weak) => f
-                .debug_tuple("Attribute")
-                .field(&weak.parent.upgrade().unwrap().data)
-                .finish(),
+            Node::Attribute(weak) => match weak.parent.upgrade() {
+                Some(parent) => f.debug_tuple("Attribute").field(&parent.data).finish(),
+                None => f
+                    .debug_tuple("Attribute")
+                    .field(&format_args!("<dropped>"))
+                    .finish(),
+            },
 
             Node::DocType(weak)
             | Node::Element(weak)
             | Node::Namespace(weak)
             | Node::Text(weak)
             | Node::Comment(weak)
-            | Node::ProcessingInstruction(weak) => f
-                .debug_tuple("Node")
-                .field(&weak.upgrade().unwrap().data)
-                .finish(),
+            | Node::ProcessingInstruction(weak) => match weak.upgrade() {
+                Some(node) => f.debug_tuple("Node").field(&node.data).finish(),
+                // The Document the node came from has been dropped.
+                None => f
+                    .debug_tuple("Node")
+                    .field(&format_args!("<dropped>"))
+                    .finish(),
+            },
         }
     }
 }
@@ -700,8 +1366,90 @@ impl Nodeset {
     pub fn is_empty(&self) -> bool {
         self.nodes.is_empty()
     }
+
+    /// Each node's outer HTML joined together. The document root has none of its own and is skipped.
+    pub fn to_html(&self) -> String {
+        self.nodes.iter().filter_map(Node::as_simple_html).collect()
+    }
+
+    /// The node which appears first in the document, wherever it is in the set.
+    pub fn first_in_document_order(&self) -> Option<&Node> {
+        let mut indexes = ChildIndexes::default();
+
+        self.nodes
+            .iter()
+            .min_by_key(|node| document_order_key(node, &mut indexes))
+    }
+
+    /// Sort the nodes into the order they appear in the document.
+    pub fn sort_document_order(&mut self) {
+        let mut indexes = ChildIndexes::default();
+
+        self.nodes
+            .sort_by_cached_key(|node| document_order_key(node, &mut indexes));
+    }
 }
 
+// Where each node is within its parent's children.
+// Every child of a parent is indexed the first time one of them is looked up, so the siblings are only walked once.
+#[derive(Default)]
+struct ChildIndexes(HashMap<*const DomNode, usize>);
+
+impl ChildIndexes {
+    fn index_of(&mut self, parent: &NodeHandle, node: &NodeHandle) -> Option<usize> {
+        if let Some(&index) = self.0.get(&Rc::as_ptr(node)) {
+            return Some(index);
+        }
+
+        let parent = with_template_contents(parent.clone());
+
+        for (index, child) in parent.children.borrow().iter().enumerate() {
+            self.0.insert(Rc::as_ptr(child), index);
+        }
+
+        self.0.get(&Rc::as_ptr(node)).copied()
+    }
+}
+
+// Child indexes from the root down to the node. Comparing keys compares document order.
+fn document_order_key(node: &Node, indexes: &mut ChildIndexes) -> Vec<usize> {
+    let mut key = Vec::new();
+
+    // Attributes come after their element and before its children.
+    if let Node::Attribute(attr) = node {
+        let index = attr
+            .parent
+            .upgrade()
+            .and_then(|parent| match &parent.data {
+                NodeData::Element { attrs, .. } => {
+                    attrs.borrow().iter().position(|a| a.name == attr.attr.name)
+                }
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        key.push(index);
+        key.push(0);
+    }
+
+    let mut handle = node.handle();
+
+    while let Some(current) = handle {
+        let parent = parent_handle(&current);
+
+        if let Some(parent) = &parent {
+            let index = indexes.index_of(parent, &current).unwrap_or_default();
+
+            key.push(index + 1);
+        }
+
+        handle = parent;
+    }
+
+    key.reverse();
+
+    key
+}
 
 impl IntoIterator for Nodeset {
     type Item = Node;
@@ -752,6 +1500,48 @@ impl Valueset {
     //
 }
 
+// html5ever keeps the children of a <template> in a separate document fragment.
+fn with_template_contents(handle: NodeHandle) -> NodeHandle {
+    let contents = match &handle.data {
+        NodeData::Element {
+            template_contents, ..
+        } => template_contents.borrow().clone(),
+        _ => None,
+    };
+
+    contents.unwrap_or(handle)
+}
+
+// Template contents are linked back to their <template> so it's found as the parent, see `link_template_contents`.
+fn parent_handle(handle: &NodeHandle) -> Option<NodeHandle> {
+    let parent = get_opt_node_from_cell(&handle.parent)?.upgrade()?;
+
+    match (&parent.data, get_opt_node_from_cell(&parent.parent)) {
+        (NodeData::Document, Some(template)) => template.upgrade(),
+        _ => Some(parent),
+    }
+}
+
+// html5ever doesn't give a template's contents fragment a parent.
+// Without one, going up from inside a template stops at the fragment as if it were the document root.
+pub(crate) fn link_template_contents(handle: &NodeHandle) {
+    let mut stack = vec![handle.clone()];
+
+    while let Some(node) = stack.pop() {
+        if let NodeData::Element {
+            template_contents, ..
+        } = &node.data
+        {
+            if let Some(contents) = template_contents.borrow().as_ref() {
+                contents.parent.set(Some(Rc::downgrade(&node)));
+                stack.push(contents.clone());
+            }
+        }
+
+        stack.extend(node.children.borrow().iter().cloned());
+    }
+}
+
 pub fn get_opt_node_from_cell(cell: &Cell<Option<WeakNodeHandle>>) -> Option<WeakNodeHandle> {
     let item = cell.take();
 